@@ -0,0 +1,482 @@
+//! Data flowing between workflow nodes during a run.
+//!
+//! Every node's output is stored in an [`ExecutionContext`] keyed by node id.
+//! Before a node runs, string values anywhere in its `data` may reference
+//! earlier outputs with `{{nodes.<id>.output}}`, optionally followed by a
+//! dotted path into the value (`{{nodes.<id>.output.items.0.title}}`).
+//! Workflow parameters supplied with the run are available the same way as
+//! `{{params.<name>}}`. Any other `{{ ... }}` is left as literal text, so
+//! node data can still contain braces meant for a model or another tool.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+const NODES_PREFIX: &str = "nodes.";
+const PARAMS_PREFIX: &str = "params.";
+
+/// Whether the text between `{{` and `}}` is a reference this module resolves.
+fn is_reference(expr: &str) -> bool {
+    expr.starts_with(NODES_PREFIX) || expr.starts_with(PARAMS_PREFIX)
+}
+
+/// How a node's most recent execution ended.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum NodeOutcomeRecord {
+    Succeeded { output: Value },
+    Failed { error: String },
+}
+
+/// @struct TokenUsage
+/// Tokens a node (or a whole run) consumed and what they cost.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl TokenUsage {
+    /// # from_counts
+    /// Usage as reported by a provider, not yet priced.
+    pub fn from_counts(prompt_tokens: u64, completion_tokens: u64) -> Self {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            cost_usd: 0.0,
+        }
+    }
+
+    /// # priced
+    /// Prices the usage at the node's `cost_per_1k_tokens`, if it has one.
+    pub fn priced(self, cost_per_1k_tokens: Option<f64>) -> Self {
+        match cost_per_1k_tokens {
+            Some(price) => TokenUsage {
+                cost_usd: price * self.total_tokens as f64 / 1000.0,
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        self.cost_usd += other.cost_usd;
+    }
+}
+
+/// @struct NodeResult
+/// What a run records about each node that executed, for run history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeResult {
+    pub node_type: String,
+    pub started_at: String,
+    pub finished_at: String,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    #[serde(flatten)]
+    pub outcome: NodeOutcomeRecord,
+}
+
+/// @struct ContextSnapshot
+/// A serializable copy of an [`ExecutionContext`], used for checkpoints.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ContextSnapshot {
+    pub outputs: HashMap<String, Value>,
+    pub results: HashMap<String, NodeResult>,
+    pub iterations: HashMap<String, u32>,
+    #[serde(default)]
+    pub parameters: serde_json::Map<String, Value>,
+    #[serde(default)]
+    pub usage: TokenUsage,
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// @struct ExecutionContext
+/// Shared, cloneable store of node outputs for a single run.
+#[derive(Clone, Default)]
+pub struct ExecutionContext {
+    outputs: Arc<Mutex<HashMap<String, Value>>>,
+    /// Latest result per node; a node inside a loop keeps its last iteration.
+    results: Arc<Mutex<HashMap<String, NodeResult>>>,
+    /// Iterations started so far, per `loop` node.
+    iterations: Arc<Mutex<HashMap<String, u32>>>,
+    parameters: Arc<Mutex<serde_json::Map<String, Value>>>,
+    /// Token usage summed over every node execution, loop iterations included.
+    usage: Arc<Mutex<TokenUsage>>,
+    /// Passed to providers that support seeded sampling, for reproducible runs.
+    seed: Arc<Mutex<Option<u64>>>,
+}
+
+impl ExecutionContext {
+    pub fn iteration(&self, loop_id: &str) -> u32 {
+        self.iterations
+            .lock()
+            .unwrap()
+            .get(loop_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Counts a new pass of `loop_id` and returns its 1-based number.
+    pub fn begin_iteration(&self, loop_id: &str) -> u32 {
+        let mut iterations = self.iterations.lock().unwrap();
+        let count = iterations.entry(loop_id.to_string()).or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    pub fn reset_iterations(&self, loop_id: &str) {
+        self.iterations.lock().unwrap().remove(loop_id);
+    }
+
+    pub fn set_output(&self, node_id: &str, output: Value) {
+        self.outputs
+            .lock()
+            .unwrap()
+            .insert(node_id.to_string(), output);
+    }
+
+    pub fn output(&self, node_id: &str) -> Option<Value> {
+        self.outputs.lock().unwrap().get(node_id).cloned()
+    }
+
+    pub fn record_result(&self, node_id: &str, result: NodeResult) {
+        if let Some(usage) = &result.usage {
+            self.usage.lock().unwrap().add(usage);
+        }
+        self.results
+            .lock()
+            .unwrap()
+            .insert(node_id.to_string(), result);
+    }
+
+    pub fn results(&self) -> HashMap<String, NodeResult> {
+        self.results.lock().unwrap().clone()
+    }
+
+    pub fn usage(&self) -> TokenUsage {
+        *self.usage.lock().unwrap()
+    }
+
+    pub fn snapshot(&self) -> ContextSnapshot {
+        ContextSnapshot {
+            outputs: self.outputs.lock().unwrap().clone(),
+            results: self.results(),
+            iterations: self.iterations.lock().unwrap().clone(),
+            parameters: self.parameters.lock().unwrap().clone(),
+            usage: self.usage(),
+            seed: self.seed(),
+        }
+    }
+
+    /// Replaces the stored state with `snapshot`, e.g. when resuming a run.
+    pub fn restore(&self, snapshot: ContextSnapshot) {
+        *self.outputs.lock().unwrap() = snapshot.outputs;
+        *self.results.lock().unwrap() = snapshot.results;
+        *self.iterations.lock().unwrap() = snapshot.iterations;
+        *self.parameters.lock().unwrap() = snapshot.parameters;
+        *self.usage.lock().unwrap() = snapshot.usage;
+        *self.seed.lock().unwrap() = snapshot.seed;
+    }
+
+    /// A context for one item of a `map` node. It starts from a copy of the
+    /// outputs so that items cannot see each other's, but shares results,
+    /// parameters, usage and the seed with this one.
+    pub fn scope(&self) -> ExecutionContext {
+        ExecutionContext {
+            outputs: Arc::new(Mutex::new(self.outputs.lock().unwrap().clone())),
+            results: self.results.clone(),
+            iterations: Arc::default(),
+            parameters: self.parameters.clone(),
+            usage: self.usage.clone(),
+            seed: self.seed.clone(),
+        }
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        *self.seed.lock().unwrap()
+    }
+
+    pub fn set_seed(&self, seed: Option<u64>) {
+        *self.seed.lock().unwrap() = seed;
+    }
+
+    pub fn set_parameters(&self, parameters: serde_json::Map<String, Value>) {
+        *self.parameters.lock().unwrap() = parameters;
+    }
+
+    /// # resolve
+    /// Returns a copy of `value` with every template placeholder substituted.
+    /// A string consisting of a single placeholder takes the referenced value
+    /// as-is (so objects and numbers keep their type); placeholders embedded
+    /// in longer text are rendered as text.
+    pub fn resolve(&self, value: &Value) -> Result<Value, String> {
+        match value {
+            Value::String(s) => self.resolve_str(s),
+            Value::Array(items) => items
+                .iter()
+                .map(|item| self.resolve(item))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+            Value::Object(map) => map
+                .iter()
+                .map(|(k, v)| self.resolve(v).map(|v| (k.clone(), v)))
+                .collect::<Result<serde_json::Map<_, _>, _>>()
+                .map(Value::Object),
+            other => Ok(other.clone()),
+        }
+    }
+
+    fn resolve_str(&self, s: &str) -> Result<Value, String> {
+        let trimmed = s.trim();
+        if trimmed.starts_with(OPEN)
+            && trimmed.ends_with(CLOSE)
+            && trimmed.matches(OPEN).count() == 1
+        {
+            let expr = trimmed[OPEN.len()..trimmed.len() - CLOSE.len()].trim();
+            if !is_reference(expr) {
+                return Ok(Value::String(s.to_string()));
+            }
+            return self.lookup(expr);
+        }
+
+        let mut rendered = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(start) = rest.find(OPEN) {
+            let Some(len) = rest[start..].find(CLOSE) else {
+                break;
+            };
+            let end = start + len + CLOSE.len();
+            let expr = rest[start + OPEN.len()..start + len].trim();
+            if !is_reference(expr) {
+                rendered.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
+            }
+            rendered.push_str(&rest[..start]);
+            match self.lookup(expr)? {
+                Value::String(text) => rendered.push_str(&text),
+                other => rendered.push_str(&other.to_string()),
+            }
+            rest = &rest[end..];
+        }
+        rendered.push_str(rest);
+        Ok(Value::String(rendered))
+    }
+
+    fn lookup(&self, expr: &str) -> Result<Value, String> {
+        if let Some(reference) = expr.strip_prefix(PARAMS_PREFIX) {
+            let (name, path) = reference.split_once('.').unwrap_or((reference, ""));
+            let value = self
+                .parameters
+                .lock()
+                .unwrap()
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Missing workflow parameter '{}'.", name))?;
+            return select_path(&value, path).ok_or_else(|| {
+                format!(
+                    "Template '{{{{{}}}}}' does not match the parameter's value.",
+                    expr
+                )
+            });
+        }
+        let reference = expr
+            .strip_prefix(NODES_PREFIX)
+            .ok_or_else(|| format!("Unknown template variable '{{{{{}}}}}'.", expr))?;
+        let (node_id, path) = split_output_reference(reference).ok_or_else(|| {
+            format!(
+                "Template '{{{{{}}}}}' must reference a node's .output.",
+                expr
+            )
+        })?;
+        let output = self.output(node_id).ok_or_else(|| {
+            format!(
+                "Template '{{{{{}}}}}' references node '{}', which has not produced output. Only upstream nodes can be referenced.",
+                expr, node_id
+            )
+        })?;
+        select_path(&output, path).ok_or_else(|| {
+            format!(
+                "Template '{{{{{}}}}}' does not match the node's output.",
+                expr
+            )
+        })
+    }
+}
+
+/// Names of the workflow parameters referenced by templates anywhere in `value`.
+pub fn referenced_parameters(value: &Value) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    collect_parameters(value, &mut names);
+    names
+}
+
+fn collect_parameters(value: &Value, names: &mut BTreeSet<String>) {
+    match value {
+        Value::String(s) => {
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find(OPEN) {
+                let Some(len) = rest[start..].find(CLOSE) else {
+                    break;
+                };
+                let expr = rest[start + OPEN.len()..start + len].trim();
+                if let Some(reference) = expr.strip_prefix(PARAMS_PREFIX) {
+                    let name = reference.split('.').next().unwrap_or(reference);
+                    names.insert(name.to_string());
+                }
+                rest = &rest[start + len + CLOSE.len()..];
+            }
+        }
+        Value::Array(items) => items
+            .iter()
+            .for_each(|item| collect_parameters(item, names)),
+        Value::Object(map) => map
+            .values()
+            .for_each(|item| collect_parameters(item, names)),
+        _ => {}
+    }
+}
+
+/// Splits `<id>.output.<path>` (without the `nodes.` prefix) into the node id
+/// and the path into its output. `.output` must be a whole segment, so
+/// `a.outputs` is no reference, while `x.output2.output` is one to node
+/// `x.output2`.
+pub fn split_output_reference(reference: &str) -> Option<(&str, &str)> {
+    const OUTPUT: &str = ".output";
+    reference.match_indices(OUTPUT).find_map(|(idx, _)| {
+        let rest = &reference[idx + OUTPUT.len()..];
+        if rest.is_empty() {
+            Some((&reference[..idx], rest))
+        } else {
+            rest.strip_prefix('.').map(|path| (&reference[..idx], path))
+        }
+    })
+}
+
+/// Walks a dotted path (`a.b.0.c`) into a JSON value; numeric segments index arrays.
+pub fn select_path(value: &Value, path: &str) -> Option<Value> {
+    if path.is_empty() {
+        return Some(value.clone());
+    }
+    let mut current = value;
+    for segment in path.split('.') {
+        current = match current {
+            Value::Object(map) => map.get(segment)?,
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(current.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> ExecutionContext {
+        let context = ExecutionContext::default();
+        context.set_output(
+            "fetch",
+            json!({ "count": 3, "items": [{ "title": "first" }] }),
+        );
+        context.set_parameters(json!({ "topic": "rust" }).as_object().unwrap().clone());
+        context
+    }
+
+    #[test]
+    fn lone_placeholder_keeps_the_value_type() {
+        let context = context();
+        assert_eq!(
+            context.resolve(&json!("{{ nodes.fetch.output.count }}")),
+            Ok(json!(3))
+        );
+        assert_eq!(
+            context.resolve(&json!("{{nodes.fetch.output}}")),
+            Ok(json!({ "count": 3, "items": [{ "title": "first" }] }))
+        );
+    }
+
+    #[test]
+    fn embedded_placeholders_render_as_text() {
+        assert_eq!(
+            context().resolve(&json!(
+                "{{params.topic}}: {{nodes.fetch.output.items.0.title}} of {{nodes.fetch.output.count}}"
+            )),
+            Ok(json!("rust: first of 3"))
+        );
+    }
+
+    #[test]
+    fn resolves_inside_arrays_and_objects() {
+        assert_eq!(
+            context().resolve(&json!({ "list": ["{{params.topic}}", 1], "flag": true })),
+            Ok(json!({ "list": ["rust", 1], "flag": true }))
+        );
+    }
+
+    #[test]
+    fn other_braces_are_left_alone() {
+        let context = context();
+        assert_eq!(
+            context.resolve(&json!("{{ user }}")),
+            Ok(json!("{{ user }}"))
+        );
+        assert_eq!(
+            context.resolve(&json!("Hi {{name}}, about {{params.topic}}")),
+            Ok(json!("Hi {{name}}, about rust"))
+        );
+    }
+
+    #[test]
+    fn missing_references_are_errors() {
+        let context = context();
+        assert!(context.resolve(&json!("{{nodes.later.output}}")).is_err());
+        assert!(context.resolve(&json!("{{nodes.fetch}}")).is_err());
+        assert!(context.resolve(&json!("{{params.missing}}")).is_err());
+        assert!(context
+            .resolve(&json!("{{nodes.fetch.output.nope}}"))
+            .is_err());
+    }
+
+    #[test]
+    fn output_must_be_a_whole_segment() {
+        let context = context();
+        context.set_output("x.output2", json!({ "ok": true }));
+        assert!(context.resolve(&json!("{{nodes.fetch.outputs}}")).is_err());
+        assert!(context
+            .resolve(&json!("{{nodes.fetch.outputfoo.count}}"))
+            .is_err());
+        assert_eq!(
+            context.resolve(&json!("{{nodes.x.output2.output.ok}}")),
+            Ok(json!(true))
+        );
+        assert_eq!(split_output_reference("a.output"), Some(("a", "")));
+        assert_eq!(split_output_reference("a.output.b.0"), Some(("a", "b.0")));
+        assert_eq!(split_output_reference("a.outputs"), None);
+    }
+
+    #[test]
+    fn finds_referenced_parameters() {
+        let names = referenced_parameters(&json!({
+            "prompt": "{{params.topic}} and {{ params.tone.style }}",
+            "other": ["{{nodes.a.output}}", "{{user}}"]
+        }));
+        assert_eq!(
+            names.into_iter().collect::<Vec<_>>(),
+            vec!["tone".to_string(), "topic".to_string()]
+        );
+    }
+}
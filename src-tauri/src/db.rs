@@ -0,0 +1,143 @@
+//! The backend's own SQLite database, for data the Rust side writes itself
+//! (run history and the like). It lives next to the other app data files.
+//!
+//! Schema changes are appended to [`MIGRATIONS`]; the index of the last one
+//! applied is kept in `PRAGMA user_version`, so never edit or reorder
+//! existing entries.
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+
+const DB_FILE: &str = "squadaid.db";
+
+const MIGRATIONS: &[&str] = &[
+    // 1: run history
+    "CREATE TABLE workflow_runs (
+        run_id TEXT PRIMARY KEY NOT NULL,
+        workflow_id TEXT,
+        status TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        finished_at TEXT,
+        error TEXT,
+        node_results TEXT NOT NULL DEFAULT '{}'
+    );
+    CREATE INDEX workflow_runs_workflow_id ON workflow_runs (workflow_id, started_at);",
+    // 2: events for replay, appended as they are emitted
+    "CREATE TABLE run_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        run_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        envelope TEXT NOT NULL
+    );
+    CREATE INDEX run_events_run_id ON run_events (run_id, id);",
+    // 3: crash recovery
+    "ALTER TABLE workflow_runs ADD COLUMN graph_state TEXT;
+    ALTER TABLE workflow_runs ADD COLUMN checkpoint TEXT;",
+    // 4: named workflows
+    "CREATE TABLE workflows (
+        id TEXT PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL UNIQUE,
+        graph_state TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );",
+    // 5: workflow descriptions
+    "ALTER TABLE workflows ADD COLUMN description TEXT;",
+    // 6: workflow history
+    "CREATE TABLE workflow_versions (
+        workflow_id TEXT NOT NULL REFERENCES workflows (id) ON DELETE CASCADE,
+        version INTEGER NOT NULL,
+        graph_state TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        PRIMARY KEY (workflow_id, version)
+    );",
+    // 7: debugger breakpoints
+    "CREATE TABLE workflow_breakpoints (
+        workflow_id TEXT NOT NULL REFERENCES workflows (id) ON DELETE CASCADE,
+        node_id TEXT NOT NULL,
+        PRIMARY KEY (workflow_id, node_id)
+    );",
+    // 8: persisted execution logs
+    "CREATE TABLE run_logs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        run_id TEXT NOT NULL,
+        node_id TEXT,
+        iteration INTEGER,
+        level TEXT NOT NULL,
+        message TEXT NOT NULL,
+        timestamp TEXT NOT NULL
+    );
+    CREATE INDEX run_logs_run_id ON run_logs (run_id, id);",
+    // 9: run cost and token totals
+    "ALTER TABLE workflow_runs ADD COLUMN duration_ms INTEGER;
+    ALTER TABLE workflow_runs ADD COLUMN prompt_tokens INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE workflow_runs ADD COLUMN completion_tokens INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE workflow_runs ADD COLUMN total_tokens INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE workflow_runs ADD COLUMN cost_usd REAL NOT NULL DEFAULT 0;",
+    // 10: reproducible runs
+    "ALTER TABLE workflow_runs ADD COLUMN seed INTEGER;",
+    // 11: options to resume a run with
+    "ALTER TABLE workflow_runs ADD COLUMN options TEXT;",
+];
+
+/// @struct Db
+/// Managed state wrapping the connection pool.
+pub struct Db {
+    pub pool: SqlitePool,
+}
+
+impl Db {
+    pub fn open(app: &tauri::App) -> Result<Self, String> {
+        let dir = app
+            .path_resolver()
+            .app_data_dir()
+            .ok_or_else(|| "Could not resolve app data directory".to_string())?;
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let options = SqliteConnectOptions::new()
+            .filename(dir.join(DB_FILE))
+            .create_if_missing(true)
+            .foreign_keys(true);
+
+        tauri::async_runtime::block_on(async {
+            let pool = SqlitePoolOptions::new()
+                .connect_with(options)
+                .await
+                .map_err(|e| e.to_string())?;
+            migrate(&pool).await?;
+            Ok(Db { pool })
+        })
+    }
+
+    /// A fresh in-memory database with every migration applied.
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self, String> {
+        // Every connection to `:memory:` opens its own empty database.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .map_err(|e| e.to_string())?;
+        migrate(&pool).await?;
+        Ok(Db { pool })
+    }
+}
+
+async fn migrate(pool: &SqlitePool) -> Result<(), String> {
+    let applied: i64 = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+        let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+        sqlx::raw_sql(sql)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| format!("Migration {} failed: {}", index + 1, e))?;
+        // PRAGMA does not accept bound parameters.
+        sqlx::query(&format!("PRAGMA user_version = {}", index + 1))
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        tx.commit().await.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,440 @@
+//! Every event the backend emits to the frontend is declared here.
+//!
+//! Payloads are plain structs implementing [`AppEvent`], which pins each one to
+//! a single event name and a schema version. [`RunEmitter`] wraps them in an
+//! [`Envelope`] carrying the run id and timestamp, so listeners can rely on the
+//! same top-level shape for every event while still reading payload fields
+//! (e.g. `message`) directly.
+
+use crate::run_logs::RunLogWriter;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
+
+/// A typed event payload. Bump `VERSION` whenever the payload shape changes in
+/// a way listeners would notice.
+pub trait AppEvent: Serialize + Clone {
+    const NAME: &'static str;
+    const VERSION: u32;
+}
+
+/// @struct Envelope
+/// The wire format of every emitted event: metadata plus the flattened payload.
+#[derive(Serialize, Clone)]
+pub struct Envelope<'a, E: AppEvent> {
+    pub version: u32,
+    pub run_id: &'a str,
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub payload: E,
+}
+
+/// How severe a log line is; `get_run_logs` filters on it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+}
+
+/// @struct LogPayload
+/// A free-text line for the execution log console. `node_id` is set when the
+/// line comes from a specific node, since parallel branches interleave, and
+/// `iteration` when that node is running inside a loop.
+#[derive(Clone, Serialize)]
+pub struct LogPayload {
+    pub level: LogLevel,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iteration: Option<u32>,
+}
+
+impl AppEvent for LogPayload {
+    const NAME: &'static str = "execution-log";
+    const VERSION: u32 = 2;
+}
+
+/// @struct RunQueuedPayload
+/// Emitted instead of starting right away when the maximum number of runs
+/// is already executing. `position` counts from 1 at the time of queueing.
+#[derive(Clone, Serialize)]
+pub struct RunQueuedPayload {
+    pub position: usize,
+}
+
+impl AppEvent for RunQueuedPayload {
+    const NAME: &'static str = "run-queued";
+    const VERSION: u32 = 1;
+}
+
+/// @struct StartedPayload
+/// Emitted first for every run so the frontend learns the run id it needs
+/// for control commands such as `cancel_workflow`.
+#[derive(Clone, Serialize)]
+pub struct StartedPayload {}
+
+impl AppEvent for StartedPayload {
+    const NAME: &'static str = "execution-started";
+    const VERSION: u32 = 1;
+}
+
+/// @struct CancelledPayload
+/// Emitted when a run stops early because `cancel_workflow` was called.
+#[derive(Clone, Serialize)]
+pub struct CancelledPayload {}
+
+impl AppEvent for CancelledPayload {
+    const NAME: &'static str = "execution-cancelled";
+    const VERSION: u32 = 1;
+}
+
+/// @struct PausedPayload
+/// Emitted when `pause_workflow` takes effect. `pending_node_ids` are the
+/// nodes that will start (or finish) once the run is resumed.
+#[derive(Clone, Serialize)]
+pub struct PausedPayload {
+    pub pending_node_ids: Vec<String>,
+}
+
+impl AppEvent for PausedPayload {
+    const NAME: &'static str = "execution-paused";
+    const VERSION: u32 = 1;
+}
+
+/// @struct ResumedPayload
+/// Emitted when a paused run continues.
+#[derive(Clone, Serialize)]
+pub struct ResumedPayload {}
+
+impl AppEvent for ResumedPayload {
+    const NAME: &'static str = "execution-resumed";
+    const VERSION: u32 = 1;
+}
+
+/// @struct NodeStartedPayload
+/// Emitted when a node begins executing, so the canvas can highlight it.
+#[derive(Clone, Serialize)]
+pub struct NodeStartedPayload {
+    pub node_id: String,
+    pub node_type: String,
+    pub started_at: String,
+}
+
+impl AppEvent for NodeStartedPayload {
+    const NAME: &'static str = "node-started";
+    const VERSION: u32 = 1;
+}
+
+/// @struct NodeSucceededPayload
+/// Emitted when a node finishes without error, carrying its output.
+#[derive(Clone, Serialize)]
+pub struct NodeSucceededPayload {
+    pub node_id: String,
+    pub node_type: String,
+    pub started_at: String,
+    pub finished_at: String,
+    pub duration_ms: u64,
+    pub output: serde_json::Value,
+}
+
+impl AppEvent for NodeSucceededPayload {
+    const NAME: &'static str = "node-succeeded";
+    const VERSION: u32 = 1;
+}
+
+/// @struct NodeFailedPayload
+/// Emitted when a node returns an error.
+#[derive(Clone, Serialize)]
+pub struct NodeFailedPayload {
+    pub node_id: String,
+    pub node_type: String,
+    pub started_at: String,
+    pub finished_at: String,
+    pub duration_ms: u64,
+    pub error: String,
+}
+
+impl AppEvent for NodeFailedPayload {
+    const NAME: &'static str = "node-failed";
+    const VERSION: u32 = 1;
+}
+
+/// @struct NodeSkippedPayload
+/// Emitted for nodes that never run because none of their incoming edges were
+/// taken, e.g. the unchosen side of a condition.
+#[derive(Clone, Serialize)]
+pub struct NodeSkippedPayload {
+    pub node_id: String,
+    pub node_type: String,
+}
+
+impl AppEvent for NodeSkippedPayload {
+    const NAME: &'static str = "node-skipped";
+    const VERSION: u32 = 1;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum InteractionKind {
+    ApprovalRequest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    Approve,
+    Reject,
+}
+
+impl ApprovalDecision {
+    /// The outgoing handle an approval node follows for this decision.
+    pub fn branch(self) -> &'static str {
+        match self {
+            ApprovalDecision::Approve => "approved",
+            ApprovalDecision::Reject => "rejected",
+        }
+    }
+}
+
+/// @struct Approval
+/// A decision submitted through `approve_node`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Approval {
+    pub decision: ApprovalDecision,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// @struct InteractionRequestedPayload
+/// Emitted when a node needs input from a person before it can finish.
+#[derive(Clone, Serialize)]
+pub struct InteractionRequestedPayload {
+    pub interaction_id: String,
+    pub node_id: String,
+    pub kind: InteractionKind,
+    pub prompt: String,
+    pub input: serde_json::Value,
+}
+
+impl AppEvent for InteractionRequestedPayload {
+    const NAME: &'static str = "interaction-requested";
+    const VERSION: u32 = 1;
+}
+
+/// @struct InteractionResolvedPayload
+/// Emitted once a requested interaction has been answered.
+#[derive(Clone, Serialize)]
+pub struct InteractionResolvedPayload {
+    pub interaction_id: String,
+    pub node_id: String,
+    #[serde(flatten)]
+    pub approval: Approval,
+}
+
+impl AppEvent for InteractionResolvedPayload {
+    const NAME: &'static str = "interaction-resolved";
+    const VERSION: u32 = 1;
+}
+
+/// @struct BreakpointHitPayload
+/// Emitted when a run in step mode stops before a node with a breakpoint.
+/// The node starts once `step_run` releases it.
+#[derive(Clone, Serialize)]
+pub struct BreakpointHitPayload {
+    pub node_id: String,
+}
+
+impl AppEvent for BreakpointHitPayload {
+    const NAME: &'static str = "breakpoint-hit";
+    const VERSION: u32 = 1;
+}
+
+/// @struct ProgressPayload
+/// Emitted whenever a node finishes or is skipped. Nodes re-run by a loop
+/// are only counted once, so `percent` never goes backwards.
+#[derive(Clone, Serialize)]
+pub struct ProgressPayload {
+    pub completed_nodes: usize,
+    pub total_nodes: usize,
+    pub percent: u8,
+}
+
+impl AppEvent for ProgressPayload {
+    const NAME: &'static str = "execution-progress";
+    const VERSION: u32 = 1;
+}
+
+/// @struct FinishedPayload
+/// The payload for the event indicating the workflow has completed.
+#[derive(Clone, Serialize)]
+pub struct FinishedPayload {
+    pub success: bool,
+}
+
+impl AppEvent for FinishedPayload {
+    const NAME: &'static str = "execution-finished";
+    const VERSION: u32 = 1;
+}
+
+/// @struct RecordedEvent
+/// An event exactly as it was sent, kept so a run can be replayed later.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedEvent {
+    pub name: String,
+    pub envelope: serde_json::Value,
+}
+
+/// Where a [`RunEmitter`] sends its events.
+#[derive(Clone)]
+enum Sink {
+    Window(tauri::Window),
+    /// Collects events in memory, so tests can run workflows without an app.
+    #[cfg(test)]
+    Recorded(Arc<Mutex<Vec<RecordedEvent>>>),
+}
+
+/// @struct RunEmitter
+/// Emits events for a single workflow run, stamping each one with the run id
+/// and appending it to the run's stored history.
+#[derive(Clone)]
+pub struct RunEmitter {
+    sink: Sink,
+    run_id: String,
+    iteration: Option<u32>,
+}
+
+impl RunEmitter {
+    pub fn new(window: tauri::Window) -> Self {
+        RunEmitter::with_run_id(window, uuid::Uuid::new_v4().to_string())
+    }
+
+    /// An emitter for an existing run id, e.g. when a run is resumed.
+    pub fn with_run_id(window: tauri::Window, run_id: String) -> Self {
+        RunEmitter {
+            sink: Sink::Window(window),
+            run_id,
+            iteration: None,
+        }
+    }
+
+    /// An emitter that keeps every event in the returned list instead of
+    /// sending it. It has no app, so nodes that need one cannot run.
+    #[cfg(test)]
+    pub fn recorded() -> (Self, Arc<Mutex<Vec<RecordedEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let emitter = RunEmitter {
+            sink: Sink::Recorded(events.clone()),
+            run_id: uuid::Uuid::new_v4().to_string(),
+            iteration: None,
+        };
+        (emitter, events)
+    }
+
+    /// A copy of this emitter whose node log lines carry a loop iteration.
+    pub fn with_iteration(&self, iteration: Option<u32>) -> Self {
+        RunEmitter {
+            iteration,
+            ..self.clone()
+        }
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    /// The app the run belongs to, for reaching its managed state.
+    pub fn app_handle(&self) -> tauri::AppHandle {
+        match &self.sink {
+            Sink::Window(window) => window.app_handle(),
+            #[cfg(test)]
+            Sink::Recorded(_) => panic!("a recorded run has no app"),
+        }
+    }
+
+    pub fn emit<E: AppEvent>(&self, payload: E) -> Result<(), String> {
+        let envelope = Envelope {
+            version: E::VERSION,
+            run_id: &self.run_id,
+            timestamp: Utc::now().to_rfc3339(),
+            payload,
+        };
+        let envelope = serde_json::to_value(envelope).map_err(|e| e.to_string())?;
+        match &self.sink {
+            Sink::Window(window) => {
+                if let Some(writer) = window.try_state::<RunLogWriter>() {
+                    writer.record_event(&self.run_id, E::NAME, &envelope);
+                }
+                window.emit(E::NAME, envelope).map_err(|e| e.to_string())
+            }
+            #[cfg(test)]
+            Sink::Recorded(events) => {
+                events.lock().unwrap().push(RecordedEvent {
+                    name: E::NAME.to_string(),
+                    envelope,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Emits a log line and stores it in the run's persisted log.
+    fn emit_log(&self, payload: LogPayload) -> Result<(), String> {
+        let writer = match &self.sink {
+            Sink::Window(window) => window.try_state::<RunLogWriter>(),
+            #[cfg(test)]
+            Sink::Recorded(_) => None,
+        };
+        if let Some(writer) = writer {
+            writer.record(
+                &self.run_id,
+                payload.node_id.as_deref(),
+                payload.iteration,
+                payload.level,
+                &payload.message,
+            );
+        }
+        self.emit(payload)
+    }
+
+    /// Shorthand for emitting a run-level `LogPayload`.
+    pub fn log(&self, level: LogLevel, message: impl Into<String>) -> Result<(), String> {
+        self.emit_log(LogPayload {
+            level,
+            message: message.into(),
+            node_id: None,
+            iteration: None,
+        })
+    }
+
+    /// Shorthand for emitting a `LogPayload` tagged with the originating node.
+    pub fn node_log(
+        &self,
+        node_id: &str,
+        level: LogLevel,
+        message: impl Into<String>,
+    ) -> Result<(), String> {
+        self.emit_log(LogPayload {
+            level,
+            message: message.into(),
+            node_id: Some(node_id.to_string()),
+            iteration: self.iteration,
+        })
+    }
+}
@@ -0,0 +1,2421 @@
+use crate::context::{
+    referenced_parameters, ContextSnapshot, ExecutionContext, NodeOutcomeRecord, NodeResult,
+    TokenUsage,
+};
+use crate::db::Db;
+use crate::events::{
+    Approval, ApprovalDecision, BreakpointHitPayload, CancelledPayload, FinishedPayload,
+    InteractionKind, InteractionRequestedPayload, InteractionResolvedPayload, LogLevel,
+    NodeFailedPayload, NodeSkippedPayload, NodeStartedPayload, NodeSucceededPayload, PausedPayload,
+    ProgressPayload, ResumedPayload, RunEmitter, StartedPayload,
+};
+use crate::expression::{self, Scope};
+use crate::metrics::AppMetrics;
+use crate::notifications;
+use crate::providers;
+use crate::queue::{BusyGuard, RunSlot};
+use crate::runs;
+use crate::tray;
+use crate::validation::{self, Severity};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::Manager;
+use tokio::sync::{oneshot, watch, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+/// Used when the caller does not specify how many nodes may run at once.
+pub const DEFAULT_MAX_PARALLELISM: usize = 4;
+
+/// Used when a `loop` node does not set `max_iterations`.
+const DEFAULT_LOOP_ITERATIONS: u32 = 10;
+
+/// Hard upper bound on `max_iterations`, so a typo cannot spin forever.
+const MAX_LOOP_ITERATIONS: u32 = 1_000;
+
+const CONDITION_NODE: &str = "condition";
+const LOOP_NODE: &str = "loop";
+const LOOP_BODY: &str = "body";
+const LOOP_DONE: &str = "done";
+const APPROVAL_NODE: &str = "approval";
+/// Uses the same `"body"` / `"done"` handles as `loop`.
+const MAP_NODE: &str = "map";
+const JOIN_NODE: &str = "join";
+
+/// What a `join` node asks the model for when `data.prompt` is not set.
+const DEFAULT_JOIN_PROMPT: &str =
+    "Combine the following results from parallel steps into a single answer.";
+
+/// Used when a `map` node does not set `concurrency`: items run one at a time.
+const DEFAULT_MAP_CONCURRENCY: usize = 1;
+
+/// The branch a failed node takes when it is routed to its error handler.
+const ERROR_BRANCH: &str = "error";
+
+// --- Data Structures ---
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Node {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub node_type: String,
+    pub data: Value,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Edge {
+    pub id: String,
+    pub source: String,
+    pub target: String,
+    /// The source handle the edge leaves from. On `condition` nodes this is
+    /// the branch (`"true"` / `"false"`) the edge belongs to; on `loop` and
+    /// `map` nodes it is `"body"` or `"done"`, and on `approval` nodes
+    /// `"approved"` or `"rejected"`.
+    #[serde(rename = "sourceHandle", default)]
+    pub source_handle: Option<String>,
+    /// Marks an edge that is only followed when its source fails, leading to
+    /// a fallback or cleanup node. Edges leaving an `"error"` handle count as
+    /// flagged too.
+    #[serde(rename = "onError", default)]
+    pub on_error: bool,
+}
+
+/// An outgoing edge as seen by the scheduler.
+#[derive(Debug, Clone)]
+pub(crate) struct OutEdge {
+    pub(crate) target: String,
+    /// The branch this edge belongs to. Unlabelled edges leaving a `condition`
+    /// node default to `"true"`, and those leaving a `loop` node to `"done"`.
+    handle: Option<String>,
+    /// Set for edges that close a loop by pointing back at a `loop` node.
+    pub(crate) back: bool,
+    /// Set for edges followed only when the source fails.
+    pub(crate) on_error: bool,
+}
+
+impl OutEdge {
+    fn follows(&self, branch: Option<&str>) -> bool {
+        match branch {
+            None => !self.on_error,
+            Some(branch) => self.handle.as_deref() == Some(branch),
+        }
+    }
+}
+
+/// What a node hands back to the scheduler: `Some(branch)` restricts which
+/// outgoing edges are followed, `None` follows all of them.
+type Branch = Option<String>;
+
+/// What the executor does when a node fails (including timing out).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorPolicy {
+    /// Fail the run, ignoring `on_error` edges.
+    Abort,
+    /// Treat the failed node like a skipped one and keep going; downstream
+    /// nodes run only if another of their inputs was taken.
+    Continue,
+    /// Follow the node's `on_error` edges, failing the run if it has none.
+    #[default]
+    RouteToErrorHandler,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GraphState {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    /// Explicitly declared inputs. Parameters that are only referenced by
+    /// `{{params.<name>}}` templates are required and have no description.
+    #[serde(default)]
+    pub parameters: Vec<ParameterDecl>,
+    #[serde(rename = "errorPolicy", default)]
+    pub error_policy: ErrorPolicy,
+    /// Timeout for nodes that do not set their own `data.timeout_secs`.
+    /// Zero or unset lets them run for as long as they need.
+    #[serde(rename = "timeoutSecs", default)]
+    pub timeout_secs: Option<f64>,
+}
+
+/// @struct ParameterDecl
+/// An input a workflow accepts when it is run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParameterDecl {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Used when the run does not supply the parameter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+}
+
+impl GraphState {
+    /// # parameters
+    /// Every parameter the workflow uses: the declared ones first, then any
+    /// others referenced from node data.
+    pub fn parameters(&self) -> Vec<ParameterDecl> {
+        let mut parameters = self.parameters.clone();
+        for node in &self.nodes {
+            for name in referenced_parameters(&node.data) {
+                if !parameters.iter().any(|p| p.name == name) {
+                    parameters.push(ParameterDecl {
+                        name,
+                        description: None,
+                        default: None,
+                    });
+                }
+            }
+        }
+        parameters
+    }
+
+    /// The workflow's default node timeout, if it has a positive one.
+    fn default_node_timeout(&self) -> Option<Duration> {
+        self.timeout_secs
+            .filter(|secs| *secs > 0.0)
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+    }
+
+    /// Fills in defaults and checks that every required parameter is set.
+    fn resolve_parameters(
+        &self,
+        mut supplied: serde_json::Map<String, Value>,
+    ) -> Result<serde_json::Map<String, Value>, String> {
+        let mut missing = Vec::new();
+        for decl in self.parameters() {
+            if supplied.contains_key(&decl.name) {
+                continue;
+            }
+            match decl.default {
+                Some(default) => {
+                    supplied.insert(decl.name, default);
+                }
+                None => missing.push(decl.name),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(format!(
+                "Missing workflow parameters: {}.",
+                missing.join(", ")
+            ));
+        }
+        Ok(supplied)
+    }
+}
+
+/// What a spawned node task reports back to the scheduler. A `None` result
+/// means the node never ran because the run was cancelled while it waited.
+type NodeOutcome = (String, Option<Result<Branch, String>>, Duration);
+
+/// What a map item task reports back: its index and result, plus the guard
+/// that keeps the run's slot until the map node has taken the result.
+type MapItemOutcome = (usize, Result<Value, String>, BusyGuard);
+
+/// How a run ended when it did not fail outright.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    Completed,
+    Cancelled,
+}
+
+/// @struct RunControl
+/// Handles shared between a running workflow and the commands that steer it.
+#[derive(Clone)]
+pub struct RunControl {
+    pub cancel: CancellationToken,
+    paused: Arc<watch::Sender<bool>>,
+    /// Nodes that were scheduled but not yet finished when the run was last
+    /// paused, so the UI can show where execution is parked.
+    paused_at: Arc<Mutex<Vec<String>>>,
+    /// Approval requests waiting for `approve_node`, keyed by interaction id
+    /// with the node that asked. One node can wait several times at once
+    /// when it sits in a map body.
+    approvals: Arc<Mutex<HashMap<String, (String, oneshot::Sender<Approval>)>>>,
+    /// Nodes stopped at a breakpoint, oldest first, with what releases them.
+    held: Arc<Mutex<VecDeque<(String, oneshot::Sender<()>)>>>,
+    /// The run's queue slot, given back while every node waits on a person.
+    pub slot: RunSlot,
+}
+
+impl RunControl {
+    fn new() -> Self {
+        RunControl {
+            cancel: CancellationToken::new(),
+            paused: Arc::new(watch::channel(false).0),
+            paused_at: Arc::new(Mutex::new(Vec::new())),
+            approvals: Arc::default(),
+            held: Arc::default(),
+            slot: RunSlot::default(),
+        }
+    }
+
+    /// Delivers a decision to a waiting approval node. Without an
+    /// `interaction_id` the node must have exactly one request waiting.
+    pub fn decide(
+        &self,
+        node_id: &str,
+        interaction_id: Option<&str>,
+        approval: Approval,
+    ) -> Result<(), String> {
+        let mut approvals = self.approvals.lock().unwrap();
+        // Requests whose node was aborted, e.g. with the rest of a map, are
+        // never answered.
+        approvals.retain(|_, (_, waiting)| !waiting.is_closed());
+        let interaction_id = match interaction_id {
+            Some(id) => id.to_string(),
+            None => {
+                let mut pending = approvals
+                    .iter()
+                    .filter(|(_, (waiting_node, _))| waiting_node == node_id)
+                    .map(|(id, _)| id.clone());
+                match (pending.next(), pending.next()) {
+                    (Some(id), None) => id,
+                    (None, _) => {
+                        return Err(format!("Node '{}' is not waiting for approval.", node_id))
+                    }
+                    (Some(_), Some(_)) => {
+                        return Err(format!(
+                            "Node '{}' has several approvals waiting; pass the interaction id.",
+                            node_id
+                        ))
+                    }
+                }
+            }
+        };
+        match approvals.remove(&interaction_id) {
+            Some((waiting_node, waiting)) if waiting_node == node_id => waiting
+                .send(approval)
+                .map_err(|_| format!("Node '{}' is no longer waiting for approval.", node_id)),
+            Some(entry) => {
+                approvals.insert(interaction_id.clone(), entry);
+                Err(format!(
+                    "Interaction '{}' does not belong to node '{}'.",
+                    interaction_id, node_id
+                ))
+            }
+            None => Err(format!(
+                "Node '{}' is not waiting for approval '{}'.",
+                node_id, interaction_id
+            )),
+        }
+    }
+
+    /// Approval requests still waiting for a decision.
+    pub fn pending_approvals(&self) -> usize {
+        self.approvals
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|(_, waiting)| !waiting.is_closed())
+            .count()
+    }
+
+    fn await_approval(&self, node_id: &str, interaction_id: &str) -> oneshot::Receiver<Approval> {
+        let (sender, receiver) = oneshot::channel();
+        self.approvals
+            .lock()
+            .unwrap()
+            .insert(interaction_id.to_string(), (node_id.to_string(), sender));
+        receiver
+    }
+
+    /// Lets the node that has waited longest at a breakpoint start, and
+    /// returns its id. Returns `None` if no node is stopped at one.
+    pub fn step(&self) -> Option<String> {
+        let mut held = self.held.lock().unwrap();
+        while let Some((node_id, release)) = held.pop_front() {
+            if release.send(()).is_ok() {
+                return Some(node_id);
+            }
+        }
+        None
+    }
+
+    /// Parks the caller until `step` releases `node_id` or the run is
+    /// cancelled.
+    async fn hold_at_breakpoint(&self, node_id: &str) {
+        let (release, released) = oneshot::channel();
+        self.held
+            .lock()
+            .unwrap()
+            .push_back((node_id.to_string(), release));
+        let stepped = async {
+            tokio::select! {
+                _ = released => {}
+                _ = self.cancel.cancelled() => {}
+            }
+        };
+        self.slot.parked(&self.cancel, stepped).await;
+    }
+
+    /// Takes a permit from `semaphore`, which limits nodes within the run.
+    /// Its permits are held by other nodes of the run, so waiting for one
+    /// only keeps the run's slot while those are busy.
+    async fn acquire(&self, semaphore: &Arc<Semaphore>) -> OwnedSemaphorePermit {
+        let permit = match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Ok(permit),
+            Err(_) => {
+                self.slot
+                    .parked(&self.cancel, semaphore.clone().acquire_owned())
+                    .await
+            }
+        };
+        permit.expect("node semaphores are never closed")
+    }
+
+    /// Returns `false` if the run was already paused.
+    pub fn pause(&self) -> bool {
+        self.paused
+            .send_if_modified(|paused| !std::mem::replace(paused, true))
+    }
+
+    /// Returns `false` if the run was not paused.
+    pub fn resume(&self) -> bool {
+        self.paused
+            .send_if_modified(|paused| std::mem::replace(paused, false))
+    }
+
+    pub fn paused_at(&self) -> Vec<String> {
+        self.paused_at.lock().unwrap().clone()
+    }
+
+    /// Parks the caller until the run is resumed or cancelled.
+    async fn wait_while_paused(&self) {
+        let mut paused = self.paused.subscribe();
+        if !*paused.borrow() {
+            return;
+        }
+        let resumed = async {
+            while *paused.borrow_and_update() {
+                tokio::select! {
+                    _ = paused.changed() => {}
+                    _ = self.cancel.cancelled() => return,
+                }
+            }
+        };
+        self.slot.parked(&self.cancel, resumed).await;
+    }
+}
+
+/// @struct RunRegistry
+/// Managed state tracking the control handles of every in-flight run.
+#[derive(Default)]
+pub struct RunRegistry {
+    runs: Mutex<HashMap<String, RunControl>>,
+}
+
+impl RunRegistry {
+    /// Registers a run, failing if a run with the same id is in flight.
+    pub fn try_register(&self, run_id: &str) -> Result<RunControl, String> {
+        match self.runs.lock().unwrap().entry(run_id.to_string()) {
+            Entry::Occupied(_) => Err(format!("Workflow run '{}' is still running.", run_id)),
+            Entry::Vacant(entry) => Ok(entry.insert(RunControl::new()).clone()),
+        }
+    }
+
+    pub fn get(&self, run_id: &str) -> Option<RunControl> {
+        self.runs.lock().unwrap().get(run_id).cloned()
+    }
+
+    pub fn remove(&self, run_id: &str) {
+        self.runs.lock().unwrap().remove(run_id);
+    }
+
+    fn controls(&self) -> Vec<RunControl> {
+        self.runs.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Approval requests waiting across every in-flight run.
+    pub fn pending_approvals(&self) -> usize {
+        self.controls()
+            .iter()
+            .map(RunControl::pending_approvals)
+            .sum()
+    }
+
+    /// Pauses every in-flight run; returns how many were not paused yet.
+    pub fn pause_all(&self) -> usize {
+        self.controls()
+            .iter()
+            .filter(|control| control.pause())
+            .count()
+    }
+
+    /// Resumes every paused run; returns how many were paused.
+    pub fn resume_all(&self) -> usize {
+        self.controls()
+            .iter()
+            .filter(|control| control.resume())
+            .count()
+    }
+}
+
+/// @struct ExecutionOptions
+/// Per-run knobs supplied by the caller of `run_workflow`. They are stored
+/// with the run so `resume_run` can continue under the same ones.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ExecutionOptions {
+    /// How many nodes may run at once. Approval nodes do not count.
+    pub max_parallelism: usize,
+    /// Applies to nodes that do not set their own `data.timeout_secs`,
+    /// overriding the workflow's `timeoutSecs`. `None` uses the workflow's.
+    pub node_timeout: Option<Duration>,
+    /// Values for the workflow's parameters, keyed by name.
+    pub parameters: serde_json::Map<String, Value>,
+    /// Continue an interrupted run instead of starting from the start node.
+    #[serde(skip)]
+    pub resume_from: Option<Checkpoint>,
+    /// Seed for providers that support it, so the run can be reproduced.
+    /// Stored in its own column, where run history shows it.
+    #[serde(skip)]
+    pub seed: Option<u64>,
+    /// Nodes to stop before until `step_run` is called. Empty unless the run
+    /// was started in step mode.
+    pub breakpoints: HashSet<String>,
+}
+
+impl Default for ExecutionOptions {
+    fn default() -> Self {
+        ExecutionOptions {
+            max_parallelism: DEFAULT_MAX_PARALLELISM,
+            node_timeout: None,
+            parameters: serde_json::Map::new(),
+            resume_from: None,
+            seed: None,
+            breakpoints: HashSet::new(),
+        }
+    }
+}
+
+/// @struct Checkpoint
+/// Executor state persisted after each node. Nodes listed in `pending` were
+/// spawned but had not finished, so they run again on resume.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checkpoint {
+    context: ContextSnapshot,
+    remaining: HashMap<String, usize>,
+    taken_inputs: HashMap<String, usize>,
+    back_pending: HashMap<String, usize>,
+    loops_done: HashSet<String>,
+    pending: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    completed: HashSet<String>,
+}
+
+/// @struct WorkflowGraph
+/// The parsed graph plus the derived structure the scheduler needs.
+pub(crate) struct WorkflowGraph {
+    pub(crate) nodes: Vec<Node>,
+    node_map: HashMap<String, Node>,
+    pub(crate) adj_list: HashMap<String, Vec<OutEdge>>,
+    /// Upstream nodes per node, excluding loop back-edges.
+    pub(crate) predecessors: HashMap<String, Vec<String>>,
+    /// Ids of edges whose source or target is not a node in the graph.
+    pub(crate) dangling_edges: Vec<String>,
+    /// Nodes reachable from each loop's `body` handle without passing
+    /// through the loop node itself.
+    pub(crate) loop_bodies: HashMap<String, HashSet<String>>,
+    /// Sources of the back-edges closing each loop.
+    pub(crate) back_sources: HashMap<String, Vec<String>>,
+    /// The innermost loop each body node belongs to.
+    innermost_loop: HashMap<String, String>,
+    pub(crate) error_policy: ErrorPolicy,
+    /// The subgraph each `map` node runs per item. Body nodes are not part
+    /// of `nodes`.
+    pub(crate) map_bodies: HashMap<String, Arc<MapBody>>,
+    /// Ids of edges leading into a map body from outside its map node.
+    pub(crate) map_entry_edges: Vec<String>,
+}
+
+/// @struct MapBody
+/// Everything downstream of a `map` node's `body` handle, run once per item.
+pub(crate) struct MapBody {
+    pub(crate) graph: WorkflowGraph,
+    /// Body nodes with no successors; their outputs make up an item's result.
+    sinks: Vec<String>,
+}
+
+impl WorkflowGraph {
+    /// Derives the scheduling structure without judging it; see
+    /// `validation::structural_problems` for what makes a graph runnable.
+    pub(crate) fn analyze(graph: GraphState) -> Self {
+        let (graph, map_bodies, map_entry_edges) = extract_map_bodies(graph);
+        let mut adj_list: HashMap<String, Vec<OutEdge>> = HashMap::new();
+        let mut node_map: HashMap<String, Node> = HashMap::new();
+
+        for node in graph.nodes.iter() {
+            node_map.insert(node.id.clone(), node.clone());
+            adj_list.insert(node.id.clone(), Vec::new());
+        }
+        for edge in graph.edges.iter() {
+            let Some(source) = node_map.get(&edge.source) else {
+                continue;
+            };
+            // Reported as dangling by validation and otherwise ignored; the
+            // scheduler must never see a target that is not a node.
+            if !node_map.contains_key(&edge.target) {
+                continue;
+            }
+            let default_handle = match source.node_type.as_str() {
+                CONDITION_NODE => Some("true".to_string()),
+                LOOP_NODE => Some(LOOP_DONE.to_string()),
+                APPROVAL_NODE => Some(ApprovalDecision::Approve.branch().to_string()),
+                MAP_NODE => Some(LOOP_DONE.to_string()),
+                _ => None,
+            };
+            let on_error = edge.on_error || edge.source_handle.as_deref() == Some(ERROR_BRANCH);
+            let handle = if on_error {
+                Some(ERROR_BRANCH.to_string())
+            } else {
+                edge.source_handle.clone().or(default_handle)
+            };
+            if let Some(successors) = adj_list.get_mut(&edge.source) {
+                successors.push(OutEdge {
+                    target: edge.target.clone(),
+                    handle,
+                    back: false,
+                    on_error,
+                });
+            }
+        }
+
+        // Loop bodies must be known before back-edges can be told apart from
+        // genuine cycles.
+        let loop_bodies: HashMap<String, HashSet<String>> = graph
+            .nodes
+            .iter()
+            .filter(|node| node.node_type == LOOP_NODE)
+            .map(|node| (node.id.clone(), loop_body(&node.id, &adj_list)))
+            .collect();
+        let mut back_sources: HashMap<String, Vec<String>> = HashMap::new();
+        for (source, successors) in adj_list.iter_mut() {
+            for edge in successors.iter_mut() {
+                if loop_bodies
+                    .get(&edge.target)
+                    .map_or(false, |body| body.contains(source))
+                {
+                    edge.back = true;
+                    back_sources
+                        .entry(edge.target.clone())
+                        .or_default()
+                        .push(source.clone());
+                }
+            }
+        }
+        let mut innermost_loop: HashMap<String, String> = HashMap::new();
+        for (loop_id, body) in &loop_bodies {
+            for node_id in body {
+                let replace = innermost_loop
+                    .get(node_id)
+                    .map_or(true, |current| loop_bodies[current].len() > body.len());
+                if replace {
+                    innermost_loop.insert(node_id.clone(), loop_id.clone());
+                }
+            }
+        }
+
+        let mut predecessors: HashMap<String, Vec<String>> = HashMap::new();
+        for (source, successors) in &adj_list {
+            for edge in successors.iter().filter(|edge| !edge.back) {
+                predecessors
+                    .entry(edge.target.clone())
+                    .or_default()
+                    .push(source.clone());
+            }
+        }
+        let dangling_edges = graph
+            .edges
+            .iter()
+            .filter(|edge| {
+                !node_map.contains_key(&edge.source) || !node_map.contains_key(&edge.target)
+            })
+            .map(|edge| edge.id.clone())
+            .collect();
+
+        WorkflowGraph {
+            nodes: graph.nodes,
+            node_map,
+            adj_list,
+            predecessors,
+            dangling_edges,
+            loop_bodies,
+            back_sources,
+            innermost_loop,
+            error_policy: graph.error_policy,
+            map_bodies,
+            map_entry_edges,
+        }
+    }
+
+    /// Analyzes the graph and rejects it if it has any structural error, so
+    /// nothing runs for a graph that cannot complete.
+    fn build(graph: GraphState) -> Result<Self, String> {
+        let graph = WorkflowGraph::analyze(graph);
+        match validation::structural_problems(&graph)
+            .into_iter()
+            .find(|problem| problem.severity == Severity::Error)
+        {
+            // Returned to the frontend's `catch` block, which resets the UI state.
+            Some(problem) => Err(problem.message),
+            None => Ok(graph),
+        }
+    }
+
+    pub(crate) fn start_nodes(&self) -> Vec<&Node> {
+        self.nodes
+            .iter()
+            .filter(|node| !self.predecessors.contains_key(&node.id))
+            .collect()
+    }
+
+    /// Number of forward incoming edges per node.
+    fn in_degrees(&self) -> HashMap<String, usize> {
+        in_degrees(&self.nodes, &self.adj_list)
+    }
+
+    /// Every node of the workflow, including those inside map bodies.
+    pub(crate) fn all_nodes(&self) -> Vec<&Node> {
+        let mut nodes: Vec<&Node> = self.nodes.iter().collect();
+        for body in self.map_bodies.values() {
+            nodes.extend(body.graph.all_nodes());
+        }
+        nodes
+    }
+}
+
+/// # extract_map_bodies
+/// Moves everything downstream of each outermost `map` node's `body` handle
+/// into a subgraph of its own; nested maps are handled when that subgraph is
+/// analyzed. Returns the remaining graph, the bodies, and the ids of edges
+/// that enter a body other than through its map node.
+fn extract_map_bodies(
+    mut graph: GraphState,
+) -> (GraphState, HashMap<String, Arc<MapBody>>, Vec<String>) {
+    let mut successors: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in &graph.edges {
+        successors
+            .entry(edge.source.as_str())
+            .or_default()
+            .push(edge.target.as_str());
+    }
+    let bodies: Vec<(String, HashSet<String>)> = graph
+        .nodes
+        .iter()
+        .filter(|node| node.node_type == MAP_NODE)
+        .map(|node| {
+            let mut body: HashSet<String> = HashSet::new();
+            let mut stack: Vec<&str> = graph
+                .edges
+                .iter()
+                .filter(|edge| {
+                    edge.source == node.id && edge.source_handle.as_deref() == Some(LOOP_BODY)
+                })
+                .map(|edge| edge.target.as_str())
+                .collect();
+            while let Some(node_id) = stack.pop() {
+                if node_id == node.id || !body.insert(node_id.to_string()) {
+                    continue;
+                }
+                stack.extend(successors.get(node_id).into_iter().flatten());
+            }
+            (node.id.clone(), body)
+        })
+        .filter(|(_, body)| !body.is_empty())
+        .collect();
+    // Maps nested in another map's body belong to that body's subgraph.
+    let outermost: Vec<(String, HashSet<String>)> = bodies
+        .iter()
+        .filter(|(map_id, _)| !bodies.iter().any(|(_, body)| body.contains(map_id)))
+        .cloned()
+        .collect();
+    if outermost.is_empty() {
+        return (graph, HashMap::new(), Vec::new());
+    }
+
+    let node_ids: HashSet<String> = graph.nodes.iter().map(|node| node.id.clone()).collect();
+    let owner = |node_id: &str| {
+        outermost
+            .iter()
+            .position(|(_, body)| body.contains(node_id))
+    };
+    let mut body_graphs: Vec<GraphState> = outermost
+        .iter()
+        .map(|_| GraphState {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            parameters: Vec::new(),
+            error_policy: graph.error_policy,
+            timeout_secs: graph.timeout_secs,
+        })
+        .collect();
+    let mut entry_edges = Vec::new();
+
+    let mut nodes = Vec::new();
+    for node in std::mem::take(&mut graph.nodes) {
+        match owner(&node.id) {
+            Some(index) => body_graphs[index].nodes.push(node),
+            None => nodes.push(node),
+        }
+    }
+    graph.nodes = nodes;
+    let mut edges = Vec::new();
+    for edge in std::mem::take(&mut graph.edges) {
+        match (owner(&edge.source), owner(&edge.target)) {
+            (Some(from), Some(to)) if from == to => body_graphs[from].edges.push(edge),
+            // Edges from a map into its body, and from the body back to it.
+            (None, Some(to)) if edge.source == outermost[to].0 => {}
+            (Some(from), None) if edge.target == outermost[from].0 => {}
+            (_, Some(_)) if node_ids.contains(&edge.source) => entry_edges.push(edge.id),
+            // Anything else touching a body points at a node that does not
+            // exist and is reported as dangling.
+            _ => edges.push(edge),
+        }
+    }
+    graph.edges = edges;
+
+    let map_bodies = outermost
+        .into_iter()
+        .zip(body_graphs)
+        .map(|((map_id, _), body)| {
+            let body = WorkflowGraph::analyze(body);
+            let sinks = body
+                .nodes
+                .iter()
+                .filter(|node| {
+                    body.adj_list[&node.id]
+                        .iter()
+                        .all(|edge| edge.back || edge.on_error)
+                })
+                .map(|node| node.id.clone())
+                .collect();
+            (map_id, Arc::new(MapBody { graph: body, sinks }))
+        })
+        .collect();
+    (graph, map_bodies, entry_edges)
+}
+
+/// Collects the nodes reachable from a loop's `body` edges, stopping at the
+/// loop node itself.
+fn loop_body(loop_id: &str, adj_list: &HashMap<String, Vec<OutEdge>>) -> HashSet<String> {
+    let mut body: HashSet<String> = HashSet::new();
+    let mut stack: Vec<&str> = adj_list[loop_id]
+        .iter()
+        .filter(|edge| edge.handle.as_deref() == Some(LOOP_BODY))
+        .map(|edge| edge.target.as_str())
+        .collect();
+    while let Some(node_id) = stack.pop() {
+        if node_id == loop_id || !body.insert(node_id.to_string()) {
+            continue;
+        }
+        if let Some(successors) = adj_list.get(node_id) {
+            stack.extend(successors.iter().map(|edge| edge.target.as_str()));
+        }
+    }
+    body
+}
+
+/// # topological_order
+/// Orders nodes with Kahn's algorithm so that every node appears after all of
+/// its upstream sources, which keeps diamond-shaped graphs from running a join
+/// node before each branch feeding it. Ties keep the canvas declaration order.
+/// Loop back-edges are ignored; any other cycle is an error listing the nodes
+/// that can never become ready.
+pub(crate) fn topological_order(
+    nodes: &[Node],
+    adj_list: &HashMap<String, Vec<OutEdge>>,
+) -> Result<Vec<String>, Vec<String>> {
+    let mut in_degree = in_degrees(nodes, adj_list);
+
+    let mut queue: VecDeque<&str> = nodes
+        .iter()
+        .map(|node| node.id.as_str())
+        .filter(|id| in_degree[*id] == 0)
+        .collect();
+    let mut order: Vec<String> = Vec::with_capacity(nodes.len());
+
+    while let Some(node_id) = queue.pop_front() {
+        order.push(node_id.to_string());
+        for edge in adj_list[node_id].iter().filter(|edge| !edge.back) {
+            if let Some(degree) = in_degree.get_mut(&edge.target) {
+                *degree -= 1;
+                if *degree == 0 {
+                    queue.push_back(edge.target.as_str());
+                }
+            }
+        }
+    }
+
+    if order.len() < nodes.len() {
+        let mut blocked: Vec<String> = in_degree
+            .into_iter()
+            .filter(|(_, degree)| *degree > 0)
+            .map(|(id, _)| id)
+            .collect();
+        blocked.sort_unstable();
+        return Err(blocked);
+    }
+    Ok(order)
+}
+
+/// Counts forward incoming edges per node, ignoring loop back-edges and
+/// edges that point at unknown nodes.
+fn in_degrees(nodes: &[Node], adj_list: &HashMap<String, Vec<OutEdge>>) -> HashMap<String, usize> {
+    let mut in_degree: HashMap<String, usize> =
+        nodes.iter().map(|node| (node.id.clone(), 0)).collect();
+    for successors in adj_list.values() {
+        for edge in successors.iter().filter(|edge| !edge.back) {
+            if let Some(degree) = in_degree.get_mut(&edge.target) {
+                *degree += 1;
+            }
+        }
+    }
+    in_degree
+}
+
+/// Combines upstream outputs into a node's input: a single upstream output
+/// unchanged, several as an object keyed by node id.
+fn combine_inputs(inputs: HashMap<String, Value>) -> Value {
+    match inputs.len() {
+        0 => Value::Null,
+        1 => inputs.into_values().next().unwrap_or(Value::Null),
+        _ => Value::Object(inputs.into_iter().collect()),
+    }
+}
+
+fn evaluate_expression(
+    context: &ExecutionContext,
+    node: &Node,
+    src: &str,
+    input: &Value,
+) -> Result<Value, String> {
+    let node_output = |id: &str| context.output(id);
+    let scope = Scope {
+        input,
+        node_output: &node_output,
+    };
+    expression::evaluate(src, &scope).map_err(|e| format!("Node '{}': {}", node.id, e))
+}
+
+/// # evaluate_condition
+/// Runs a `condition` node: evaluates `data.expression` against its input and
+/// selects the `"true"` or `"false"` branch. The input passes through as the
+/// node's output so downstream nodes see the same data.
+fn evaluate_condition(
+    emitter: &RunEmitter,
+    context: &ExecutionContext,
+    node: &Node,
+    input: Value,
+) -> Result<(Value, Branch), String> {
+    let src = node.data["expression"]
+        .as_str()
+        .filter(|src| !src.trim().is_empty())
+        .ok_or_else(|| format!("Condition node '{}' has no expression.", node.id))?;
+    let value = evaluate_expression(context, node, src, &input)?;
+    let branch = expression::truthy(&value).to_string();
+    emitter.node_log(
+        &node.id,
+        LogLevel::Info,
+        format!(
+            "[COND] '{}' evaluated to {}, taking the '{}' branch",
+            src, value, branch
+        ),
+    )?;
+    Ok((input, Some(branch)))
+}
+
+/// # evaluate_loop
+/// Runs a `loop` node. Each time it is reached (first from upstream, then via
+/// the back-edge at the end of every iteration) it either starts another pass
+/// over its `body` branch or exits through `done`. It exits once the optional
+/// `data.until` expression is truthy for the latest body output, or after
+/// `data.max_iterations` passes. The input passes through as the output.
+fn evaluate_loop(
+    emitter: &RunEmitter,
+    context: &ExecutionContext,
+    node: &Node,
+    input: Value,
+) -> Result<(Value, Branch), String> {
+    let max_iterations = node.data["max_iterations"]
+        .as_u64()
+        .map_or(DEFAULT_LOOP_ITERATIONS, |n| {
+            n.min(MAX_LOOP_ITERATIONS as u64) as u32
+        });
+    let completed = context.iteration(&node.id);
+
+    if completed > 0 {
+        if let Some(src) = node.data["until"]
+            .as_str()
+            .filter(|src| !src.trim().is_empty())
+        {
+            if expression::truthy(&evaluate_expression(context, node, src, &input)?) {
+                emitter.node_log(
+                    &node.id,
+                    LogLevel::Info,
+                    format!("[LOOP] '{}' met after {} iteration(s)", src, completed),
+                )?;
+                return Ok((input, Some(LOOP_DONE.to_string())));
+            }
+        }
+    }
+    if completed >= max_iterations {
+        emitter.node_log(
+            &node.id,
+            LogLevel::Info,
+            format!(
+                "[LOOP] Reached max_iterations ({}), exiting",
+                max_iterations
+            ),
+        )?;
+        return Ok((input, Some(LOOP_DONE.to_string())));
+    }
+
+    let iteration = context.begin_iteration(&node.id);
+    emitter.with_iteration(Some(iteration)).node_log(
+        &node.id,
+        LogLevel::Info,
+        format!("[LOOP] Starting iteration {}/{}", iteration, max_iterations),
+    )?;
+    Ok((input, Some(LOOP_BODY.to_string())))
+}
+
+/// # await_approval
+/// Runs an `approval` node: emits an approval-request interaction and parks
+/// until `approve_node` delivers a decision, then takes the `"approved"` or
+/// `"rejected"` branch. The input passes through as the output.
+async fn await_approval(
+    emitter: &RunEmitter,
+    control: &RunControl,
+    node: &Node,
+    input: Value,
+) -> Result<(Value, Branch), String> {
+    let interaction_id = uuid::Uuid::new_v4().to_string();
+    let decision = control.await_approval(&node.id, &interaction_id);
+    let prompt = node.data["prompt"]
+        .as_str()
+        .unwrap_or("Approve to continue the workflow.");
+    emitter.emit(InteractionRequestedPayload {
+        interaction_id: interaction_id.clone(),
+        node_id: node.id.clone(),
+        kind: InteractionKind::ApprovalRequest,
+        prompt: prompt.to_string(),
+        input: input.clone(),
+    })?;
+    emitter.node_log(
+        &node.id,
+        LogLevel::Info,
+        "[APPROVAL] Waiting for a decision",
+    )?;
+    let app = emitter.app_handle();
+    notifications::approval_needed(&app, emitter.run_id(), &node.id, prompt);
+    tray::refresh(&app);
+
+    let decided = async {
+        tokio::select! {
+            approval = decision => approval
+                .map_err(|_| format!("Approval for node '{}' was abandoned.", node.id)),
+            _ = control.cancel.cancelled() => {
+                Err(format!("Run cancelled while node '{}' awaited approval.", node.id))
+            }
+        }
+    };
+    let approval = control.slot.parked(&control.cancel, decided).await?;
+    emitter.emit(InteractionResolvedPayload {
+        interaction_id,
+        node_id: node.id.clone(),
+        approval: approval.clone(),
+    })?;
+    emitter.node_log(
+        &node.id,
+        LogLevel::Info,
+        format!("[APPROVAL] Decision: {}", approval.decision.branch()),
+    )?;
+    Ok((input, Some(approval.decision.branch().to_string())))
+}
+
+/// # join_inputs
+/// Combines the outputs of a `join` node's branches according to
+/// `data.strategy`:
+/// - `concat` (default): strings are joined with `data.separator` (a blank
+///   line by default); otherwise the outputs form one array, with array
+///   outputs flattened into it.
+/// - `merge`: object outputs are deep-merged, later branches winning.
+/// - `summarize`: `data.provider` / `data.model` are asked to combine them,
+///   guided by `data.prompt`.
+///
+/// Branches are taken in node id order; skipped branches contribute nothing.
+/// Only `summarize` reports token usage.
+async fn join_inputs(
+    emitter: &RunEmitter,
+    context: &ExecutionContext,
+    node: &Node,
+    inputs: HashMap<String, Value>,
+) -> Result<(Value, Option<TokenUsage>), String> {
+    let mut inputs: Vec<(String, Value)> = inputs.into_iter().collect();
+    inputs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    let strategy = node.data["strategy"].as_str().unwrap_or("concat");
+    emitter.node_log(
+        &node.id,
+        LogLevel::Info,
+        format!(
+            "[JOIN] Combining {} branch(es) with '{}'",
+            inputs.len(),
+            strategy
+        ),
+    )?;
+
+    let output = match strategy {
+        "concat" if inputs.iter().all(|(_, output)| output.is_string()) => {
+            let separator = node.data["separator"].as_str().unwrap_or("\n\n");
+            let texts: Vec<&str> = inputs
+                .iter()
+                .filter_map(|(_, output)| output.as_str())
+                .collect();
+            (Value::String(texts.join(separator)), None)
+        }
+        "concat" => (
+            Value::Array(
+                inputs
+                    .into_iter()
+                    .flat_map(|(_, output)| match output {
+                        Value::Array(items) => items,
+                        other => vec![other],
+                    })
+                    .collect(),
+            ),
+            None,
+        ),
+        "merge" => {
+            let mut merged = Value::Object(serde_json::Map::new());
+            for (upstream, output) in inputs {
+                if !output.is_object() {
+                    return Err(format!(
+                        "Join node '{}' can only merge objects, but '{}' produced: {}",
+                        node.id, upstream, output
+                    ));
+                }
+                merge_json(&mut merged, output);
+            }
+            (merged, None)
+        }
+        "summarize" => {
+            let (Some(provider), Some(model)) =
+                (node.data["provider"].as_str(), node.data["model"].as_str())
+            else {
+                return Err(format!(
+                    "Join node '{}' needs a provider and model to summarize.",
+                    node.id
+                ));
+            };
+            let mut prompt = node.data["prompt"]
+                .as_str()
+                .unwrap_or(DEFAULT_JOIN_PROMPT)
+                .to_string();
+            for (upstream, output) in &inputs {
+                let text = match output {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                prompt.push_str(&format!("\n\n### {}\n{}", upstream, text));
+            }
+            let app = emitter.app_handle();
+            let metrics = app.state::<AppMetrics>();
+            let completion =
+                providers::generate(&metrics, provider, model, &prompt, context.seed()).await?;
+            (Value::String(completion.text), Some(completion.usage))
+        }
+        other => {
+            return Err(format!(
+                "Join node '{}' has unknown strategy '{}'; use concat, merge or summarize.",
+                node.id, other
+            ))
+        }
+    };
+    Ok(output)
+}
+
+/// Merges `patch` into `target`, recursing into objects present in both.
+fn merge_json(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match target.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// # run_map
+/// Runs a `map` node's body once per item of its input (or of `data.items`
+/// when set), up to `data.concurrency` items at a time, and outputs the
+/// results in item order. An item's result is the output of the body's last
+/// node, or an object keyed by node id when the body ends in several. A map
+/// without a body passes the items through.
+///
+/// Boxed because the body's nodes run through `run_node` again.
+fn run_map(
+    emitter: RunEmitter,
+    context: ExecutionContext,
+    control: RunControl,
+    options: Arc<ExecutionOptions>,
+    node: Node,
+    input: Value,
+    body: Option<Arc<MapBody>>,
+) -> BoxFuture<Result<(Value, Branch), String>> {
+    Box::pin(async move {
+        let items = match node.data.get("items").cloned().unwrap_or(input) {
+            Value::Array(items) => items,
+            other => {
+                return Err(format!(
+                    "Map node '{}' needs a list to iterate over, got: {}",
+                    node.id, other
+                ))
+            }
+        };
+        let Some(body) = body else {
+            return Ok((Value::Array(items), None));
+        };
+        let concurrency = node.data["concurrency"]
+            .as_u64()
+            .map_or(DEFAULT_MAP_CONCURRENCY, |n| n.max(1) as usize);
+        emitter.node_log(
+            &node.id,
+            LogLevel::Info,
+            format!(
+                "[MAP] Running {} item(s), {} at a time",
+                items.len(),
+                concurrency
+            ),
+        )?;
+
+        let total = items.len();
+        let slots = Arc::new(Semaphore::new(concurrency));
+        let mut running: JoinSet<MapItemOutcome> = JoinSet::new();
+        for (index, item) in items.into_iter().enumerate() {
+            let slots = slots.clone();
+            let body = body.clone();
+            let emitter = emitter.with_iteration(Some(index as u32 + 1));
+            let context = context.scope();
+            let control = control.clone();
+            let options = options.clone();
+            let map_id = node.id.clone();
+            // Taken before the map node parks below, so the run keeps its
+            // slot until the items are under way.
+            let busy = control.slot.busy();
+            running.spawn(async move {
+                let _slot = control.acquire(&slots).await;
+                let result = async {
+                    emitter.node_log(
+                        &map_id,
+                        LogLevel::Info,
+                        format!("[MAP] Starting item {}/{}", index + 1, total),
+                    )?;
+                    context.set_output(&map_id, item);
+                    run_map_item(&body, &emitter, &control, &context, options, &map_id).await
+                }
+                .await;
+                (index, result, busy)
+            });
+        }
+        let mut results = vec![Value::Null; total];
+        // The items are the ones working (or waiting on a person) meanwhile.
+        while let Some(joined) = control
+            .slot
+            .parked(&control.cancel, running.join_next())
+            .await
+        {
+            // Returning drops the set, which aborts the remaining items.
+            let (index, output, _busy) = joined.map_err(|e| e.to_string())?;
+            results[index] = output?;
+        }
+        Ok((Value::Array(results), None))
+    })
+}
+
+/// Runs a map body for the item already stored as the map node's output,
+/// under the same options (timeouts, breakpoints, ...) as the rest of the run.
+async fn run_map_item(
+    body: &MapBody,
+    emitter: &RunEmitter,
+    control: &RunControl,
+    context: &ExecutionContext,
+    options: Arc<ExecutionOptions>,
+    map_id: &str,
+) -> Result<Value, String> {
+    let mut scheduler = Scheduler::new(&body.graph, emitter, control, context, options);
+    for node in body.graph.start_nodes() {
+        scheduler.spawn(&node.id, vec![map_id.to_string()]);
+    }
+    scheduler.drain().await?;
+    if control.cancel.is_cancelled() {
+        return Err(format!(
+            "Run cancelled while map node '{}' was running.",
+            map_id
+        ));
+    }
+    let outputs = body
+        .sinks
+        .iter()
+        .filter_map(|id| context.output(id).map(|output| (id.clone(), output)))
+        .collect();
+    Ok(combine_inputs(outputs))
+}
+
+/// # execute_node
+/// Runs a single node whose `data` has already had templates resolved, and
+/// returns its output plus the branch to follow. This is where agent logic
+/// will plug in; until then a node outputs `data.output` when it is set and
+/// otherwise passes its combined input through.
+///
+/// Nodes that call a model themselves also return the tokens the provider
+/// reported. A map node returns none: its body's nodes record their own.
+async fn execute_node(
+    emitter: RunEmitter,
+    context: &ExecutionContext,
+    control: &RunControl,
+    options: Arc<ExecutionOptions>,
+    node: Node,
+    inputs: HashMap<String, Value>,
+    map_body: Option<Arc<MapBody>>,
+) -> Result<(Value, Branch, Option<TokenUsage>), String> {
+    let unmetered = |(output, branch)| (output, branch, None);
+    match node.node_type.as_str() {
+        CONDITION_NODE => {
+            return evaluate_condition(&emitter, context, &node, combine_inputs(inputs))
+                .map(unmetered)
+        }
+        LOOP_NODE => {
+            return evaluate_loop(&emitter, context, &node, combine_inputs(inputs)).map(unmetered)
+        }
+        APPROVAL_NODE => {
+            return await_approval(&emitter, control, &node, combine_inputs(inputs))
+                .await
+                .map(unmetered)
+        }
+        JOIN_NODE => {
+            return join_inputs(&emitter, context, &node, inputs)
+                .await
+                .map(|(output, usage)| (output, None, usage))
+        }
+        MAP_NODE => {
+            let input = combine_inputs(inputs);
+            let control = control.clone();
+            return run_map(
+                emitter,
+                context.clone(),
+                control,
+                options,
+                node,
+                input,
+                map_body,
+            )
+            .await
+            .map(unmetered);
+        }
+        _ => {}
+    }
+
+    let node_name = node.data["name"].as_str().unwrap_or("Unnamed");
+    emitter.node_log(
+        &node.id,
+        LogLevel::Info,
+        format!(
+            "[EXEC] Visiting node '{}' (Type: {})",
+            node_name, node.node_type
+        ),
+    )?;
+
+    if let Some(output) = node.data.get("output") {
+        return Ok((output.clone(), None, None));
+    }
+    Ok((combine_inputs(inputs), None, None))
+}
+
+/// A node's own `data.timeout_secs` wins over the run-wide default; zero or
+/// a negative value disables the timeout for that node. Approval nodes wait
+/// on a person, so the default does not apply to them.
+fn node_timeout(node: &Node, default: Option<Duration>) -> Option<Duration> {
+    match node.data["timeout_secs"].as_f64() {
+        Some(secs) if secs > 0.0 => Duration::try_from_secs_f64(secs).ok(),
+        Some(_) => None,
+        None if node.node_type == APPROVAL_NODE => None,
+        None => default,
+    }
+}
+
+/// # run_node
+/// Resolves the node's templates against upstream outputs, runs it, stores
+/// its output in the context, and wraps all of it with the `node-started`
+/// and `node-succeeded` / `node-failed` lifecycle events.
+async fn run_node(
+    emitter: RunEmitter,
+    context: ExecutionContext,
+    control: RunControl,
+    options: Arc<ExecutionOptions>,
+    node: Node,
+    predecessors: Vec<String>,
+    map_body: Option<Arc<MapBody>>,
+) -> Result<Branch, String> {
+    let node_id = node.id.clone();
+    let node_type = node.node_type.clone();
+    let started_at = Utc::now();
+    emitter.emit(NodeStartedPayload {
+        node_id: node_id.clone(),
+        node_type: node_type.clone(),
+        started_at: started_at.to_rfc3339(),
+    })?;
+
+    let timeout = node_timeout(&node, options.node_timeout);
+    let mut timed_out = false;
+    let cost_per_1k_tokens = node.data["cost_per_1k_tokens"].as_f64();
+    let execution = async {
+        let node = Node {
+            data: context.resolve(&node.data)?,
+            ..node
+        };
+        let inputs: HashMap<String, Value> = predecessors
+            .into_iter()
+            .filter_map(|id| context.output(&id).map(|output| (id, output)))
+            .collect();
+        execute_node(
+            emitter.clone(),
+            &context,
+            &control,
+            options,
+            node,
+            inputs,
+            map_body,
+        )
+        .await
+    };
+    let result = match timeout {
+        Some(limit) => match tokio::time::timeout(limit, execution).await {
+            Ok(result) => result,
+            Err(_) => {
+                timed_out = true;
+                Err(format!(
+                    "Node '{}' timed out after {}s.",
+                    node_id,
+                    limit.as_secs_f64()
+                ))
+            }
+        },
+        None => execution.await,
+    };
+
+    let finished_at = Utc::now();
+    let duration_ms = (finished_at - started_at).num_milliseconds().max(0) as u64;
+    let record = |usage: Option<TokenUsage>, outcome: NodeOutcomeRecord| {
+        context.record_result(
+            &node_id,
+            NodeResult {
+                node_type: node_type.clone(),
+                started_at: started_at.to_rfc3339(),
+                finished_at: finished_at.to_rfc3339(),
+                duration_ms,
+                usage,
+                outcome,
+            },
+        )
+    };
+    match result {
+        Ok((output, branch, usage)) => {
+            context.set_output(&node_id, output.clone());
+            record(
+                usage.map(|usage| usage.priced(cost_per_1k_tokens)),
+                NodeOutcomeRecord::Succeeded {
+                    output: output.clone(),
+                },
+            );
+            emitter.emit(NodeSucceededPayload {
+                node_id,
+                node_type,
+                started_at: started_at.to_rfc3339(),
+                finished_at: finished_at.to_rfc3339(),
+                duration_ms,
+                output,
+            })?;
+            Ok(branch)
+        }
+        Err(error) => {
+            // A timeout is only a warning; the error policy decides whether
+            // the run carries on without the node.
+            let (level, tag) = if timed_out {
+                (LogLevel::Warn, "[WARN]")
+            } else {
+                (LogLevel::Error, "[ERROR]")
+            };
+            emitter.node_log(
+                &node_id,
+                level,
+                format!("{} Node '{}' failed: {}", tag, node_id, error),
+            )?;
+            record(
+                None,
+                NodeOutcomeRecord::Failed {
+                    error: error.clone(),
+                },
+            );
+            emitter.emit(NodeFailedPayload {
+                node_id,
+                node_type,
+                started_at: started_at.to_rfc3339(),
+                finished_at: finished_at.to_rfc3339(),
+                duration_ms,
+                error: error.clone(),
+            })?;
+            Err(error)
+        }
+    }
+}
+
+/// @struct Scheduler
+/// Mutable bookkeeping for one run. A node becomes ready once every upstream
+/// node has settled. It runs if at least one incoming edge was taken, and is
+/// skipped otherwise (e.g. it sits on a condition branch that was not
+/// chosen), which in turn settles its own successors. Ready nodes are spawned
+/// immediately and wait on the semaphore for a free slot.
+struct Scheduler<'a> {
+    graph: &'a WorkflowGraph,
+    emitter: &'a RunEmitter,
+    control: &'a RunControl,
+    context: ExecutionContext,
+    semaphore: Arc<Semaphore>,
+    options: Arc<ExecutionOptions>,
+    remaining: HashMap<String, usize>,
+    taken_inputs: HashMap<String, usize>,
+    /// Spawned nodes that have not reported back, with the upstream nodes
+    /// they read their inputs from.
+    in_flight: HashMap<String, Vec<String>>,
+    /// Keeps the run's slot for each spawned node until it has settled, so
+    /// finishing a node never gives the slot back before its successors are
+    /// spawned.
+    busy: HashMap<String, BusyGuard>,
+    running: JoinSet<NodeOutcome>,
+    /// Back-edges still to settle in the current iteration of each loop.
+    back_pending: HashMap<String, usize>,
+    /// Loops that have exited through `done`; later back-edge settles from
+    /// the skipped body are ignored.
+    loops_done: HashSet<String>,
+    /// Nodes that have finished or been skipped at least once.
+    completed: HashSet<String>,
+}
+
+impl<'a> Scheduler<'a> {
+    fn new(
+        graph: &'a WorkflowGraph,
+        emitter: &'a RunEmitter,
+        control: &'a RunControl,
+        context: &ExecutionContext,
+        options: Arc<ExecutionOptions>,
+    ) -> Self {
+        Scheduler {
+            graph,
+            emitter,
+            control,
+            context: context.clone(),
+            semaphore: Arc::new(Semaphore::new(options.max_parallelism.max(1))),
+            options,
+            remaining: graph.in_degrees(),
+            taken_inputs: HashMap::new(),
+            in_flight: HashMap::new(),
+            busy: HashMap::new(),
+            running: JoinSet::new(),
+            back_pending: graph
+                .back_sources
+                .iter()
+                .map(|(loop_id, sources)| (loop_id.clone(), sources.len()))
+                .collect(),
+            loops_done: HashSet::new(),
+            completed: HashSet::new(),
+        }
+    }
+
+    /// Spawns `node_id`, reading its inputs from the outputs of `upstream`.
+    fn spawn(&mut self, node_id: &str, upstream: Vec<String>) {
+        let node = self.graph.node_map[node_id].clone();
+        let iteration = self
+            .graph
+            .innermost_loop
+            .get(node_id)
+            .map(|loop_id| self.context.iteration(loop_id));
+        let emitter = self.emitter.with_iteration(iteration);
+        let context = self.context.clone();
+        let semaphore = self.semaphore.clone();
+        let control = self.control.clone();
+        let options = self.options.clone();
+        let breakpoint = self.options.breakpoints.contains(node_id);
+        let map_body = self.graph.map_bodies.get(node_id).cloned();
+        let span = tracing::info_span!(
+            "node",
+            node.id = %node.id,
+            node.name = node.data["name"].as_str().unwrap_or("Unnamed"),
+            node.r#type = %node.node_type
+        );
+        self.in_flight.insert(node.id.clone(), upstream.clone());
+        self.busy.insert(node.id.clone(), self.control.slot.busy());
+        self.running.spawn(
+            async move {
+                // Wait at the breakpoint before taking a slot, so other
+                // branches keep running meanwhile.
+                if breakpoint && !control.cancel.is_cancelled() {
+                    let hit = emitter.emit(BreakpointHitPayload {
+                        node_id: node.id.clone(),
+                    });
+                    if let Err(e) = hit {
+                        return (node.id.clone(), Some(Err(e)), Duration::ZERO);
+                    }
+                    control.hold_at_breakpoint(&node.id).await;
+                }
+                // Approval nodes wait on a person, not on the machine, so
+                // they do not take a slot from the other branches.
+                let _permit = if node.node_type == APPROVAL_NODE {
+                    None
+                } else {
+                    Some(control.acquire(&semaphore).await)
+                };
+                control.wait_while_paused().await;
+                control.slot.ready(&control.cancel).await;
+                let started = Instant::now();
+                let node_id = node.id.clone();
+                if control.cancel.is_cancelled() {
+                    return (node_id, None, started.elapsed());
+                }
+                let result =
+                    run_node(emitter, context, control, options, node, upstream, map_body).await;
+                (node_id, Some(result), started.elapsed())
+            }
+            .instrument(span),
+        );
+    }
+
+    fn spawn_forward(&mut self, node_id: &str) {
+        let upstream = self
+            .graph
+            .predecessors
+            .get(node_id)
+            .cloned()
+            .unwrap_or_default();
+        self.spawn(node_id, upstream);
+    }
+
+    fn emit_progress(&self) -> Result<(), String> {
+        let total_nodes = self.graph.nodes.len();
+        let completed_nodes = self.completed.len();
+        self.emitter.emit(ProgressPayload {
+            completed_nodes,
+            total_nodes,
+            percent: (completed_nodes * 100 / total_nodes.max(1)) as u8,
+        })
+    }
+
+    /// Captures everything needed to continue this run in a new process.
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            context: self.context.snapshot(),
+            remaining: self.remaining.clone(),
+            taken_inputs: self.taken_inputs.clone(),
+            back_pending: self.back_pending.clone(),
+            loops_done: self.loops_done.clone(),
+            pending: self.in_flight.clone(),
+            completed: self.completed.clone(),
+        }
+    }
+
+    /// Picks up from `checkpoint`, re-spawning the nodes that had not
+    /// finished when it was taken.
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.context.restore(checkpoint.context);
+        self.remaining = checkpoint.remaining;
+        self.taken_inputs = checkpoint.taken_inputs;
+        self.back_pending = checkpoint.back_pending;
+        self.loops_done = checkpoint.loops_done;
+        self.completed = checkpoint.completed;
+        let mut pending: Vec<(String, Vec<String>)> = checkpoint.pending.into_iter().collect();
+        pending.sort_unstable();
+        for (node_id, upstream) in pending {
+            if self.graph.node_map.contains_key(&node_id) {
+                self.spawn(&node_id, upstream);
+            }
+        }
+    }
+
+    /// Restores a loop's body to its pre-iteration state so it can run again.
+    fn reset_loop_body(&mut self, loop_id: &str) {
+        let graph = self.graph;
+        for node_id in &graph.loop_bodies[loop_id] {
+            // Only edges from inside the loop (or the loop node itself) fire
+            // again; anything upstream of the loop has already settled.
+            let inner_degree = graph.predecessors.get(node_id).map_or(0, |preds| {
+                preds
+                    .iter()
+                    .filter(|p| *p == loop_id || graph.loop_bodies[loop_id].contains(*p))
+                    .count()
+            });
+            self.remaining.insert(node_id.clone(), inner_degree);
+            self.taken_inputs.remove(node_id);
+            if graph.loop_bodies.contains_key(node_id) {
+                // A nested loop starts over on every pass of the outer one.
+                self.context.reset_iterations(node_id);
+                self.loops_done.remove(node_id);
+                let back_edges = graph.back_sources.get(node_id).map_or(0, Vec::len);
+                self.back_pending.insert(node_id.clone(), back_edges);
+            }
+        }
+        self.back_pending
+            .insert(loop_id.to_string(), graph.back_sources[loop_id].len());
+    }
+
+    /// Applies the workflow's error policy to a node that failed. Returns how
+    /// to settle it, or the error if the run has to fail.
+    fn recover(&self, node_id: &str, error: String) -> Result<(bool, Branch), String> {
+        let has_handler = self.graph.adj_list[node_id]
+            .iter()
+            .any(|edge| edge.on_error);
+        match self.graph.error_policy {
+            ErrorPolicy::Continue => {
+                self.emitter.node_log(
+                    node_id,
+                    LogLevel::Warn,
+                    format!("[WARN] Node '{}' failed; continuing without it.", node_id),
+                )?;
+                Ok((false, None))
+            }
+            ErrorPolicy::RouteToErrorHandler if has_handler => {
+                // Error handlers read what went wrong as their input.
+                self.context
+                    .set_output(node_id, serde_json::json!({ "error": error }));
+                self.emitter.node_log(
+                    node_id,
+                    LogLevel::Warn,
+                    format!(
+                        "[WARN] Node '{}' failed; routing to its error handler.",
+                        node_id
+                    ),
+                )?;
+                Ok((true, Some(ERROR_BRANCH.to_string())))
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// Waits for every spawned node, settling each as it finishes. Used for
+    /// map bodies, whose progress and checkpoints are the map node's.
+    async fn drain(&mut self) -> Result<(), String> {
+        let control = self.control;
+        while let Some(joined) = control
+            .slot
+            .parked(&control.cancel, self.running.join_next())
+            .await
+        {
+            let (node_id, result, _) = joined.map_err(|e| e.to_string())?;
+            self.in_flight.remove(&node_id);
+            let _busy = self.busy.remove(&node_id);
+            let Some(result) = result else { continue };
+            if self.control.cancel.is_cancelled() {
+                continue;
+            }
+            let (ran, branch) = match result {
+                Ok(branch) => (true, branch),
+                Err(error) => self.recover(&node_id, error)?,
+            };
+            self.settle(node_id, ran, branch)?;
+        }
+        Ok(())
+    }
+
+    /// Settles a finished (`ran`) or skipped node, following the edges its
+    /// branch selects and cascading through successors that end up skipped.
+    fn settle(&mut self, node_id: String, ran: bool, branch: Branch) -> Result<(), String> {
+        let graph = self.graph;
+        let mut settled: Vec<(String, bool, Branch)> = vec![(node_id, ran, branch)];
+        while let Some((settled_id, ran, branch)) = settled.pop() {
+            self.completed.insert(settled_id.clone());
+            let node_type = graph.node_map[&settled_id].node_type.as_str();
+            if node_type == LOOP_NODE && ran && branch.as_deref() == Some(LOOP_DONE) {
+                self.loops_done.insert(settled_id.clone());
+            }
+
+            for edge in &graph.adj_list[&settled_id] {
+                // While a loop keeps iterating, its exit edges stay pending.
+                if node_type == LOOP_NODE
+                    && branch.as_deref() == Some(LOOP_BODY)
+                    && !edge.follows(branch.as_deref())
+                {
+                    continue;
+                }
+
+                if edge.back {
+                    if self.loops_done.contains(&edge.target) {
+                        continue;
+                    }
+                    let pending = self.back_pending.entry(edge.target.clone()).or_insert(1);
+                    *pending = pending.saturating_sub(1);
+                    if *pending == 0 {
+                        self.reset_loop_body(&edge.target);
+                        let sources = graph.back_sources[&edge.target].clone();
+                        self.spawn(&edge.target, sources);
+                    }
+                    continue;
+                }
+
+                if ran && edge.follows(branch.as_deref()) {
+                    *self.taken_inputs.entry(edge.target.clone()).or_insert(0) += 1;
+                }
+                let Some(degree) = self.remaining.get_mut(&edge.target) else {
+                    continue;
+                };
+                *degree = degree.saturating_sub(1);
+                if *degree > 0 {
+                    continue;
+                }
+                if self.taken_inputs.get(&edge.target).copied().unwrap_or(0) > 0 {
+                    self.spawn_forward(&edge.target);
+                } else {
+                    self.emitter.emit(NodeSkippedPayload {
+                        node_id: edge.target.clone(),
+                        node_type: graph.node_map[&edge.target].node_type.clone(),
+                    })?;
+                    settled.push((edge.target.clone(), false, None));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// # execute_workflow
+/// Parses the graph, rejects structurally invalid workflows, then runs every
+/// node once all of its upstream nodes have finished. Every node without
+/// incoming edges is a root and all roots start together; independent
+/// branches run concurrently, bounded by `options.max_parallelism`.
+/// Cancellation and pausing are cooperative: both are checked before each
+/// node starts, and nodes already running are allowed to finish. A checkpoint
+/// is saved after every node so `resume_run` can continue after a crash.
+/// What happens when a node fails is up to the graph's [`ErrorPolicy`].
+pub async fn execute_workflow(
+    emitter: &RunEmitter,
+    metrics: &AppMetrics,
+    db: &Db,
+    control: &RunControl,
+    context: &ExecutionContext,
+    graph_state_json: String,
+    mut options: ExecutionOptions,
+) -> Result<RunOutcome, String> {
+    emitter.emit(StartedPayload {})?;
+
+    // --- Setup Phase ---
+    let graph: GraphState = serde_json::from_str(&graph_state_json).map_err(|e| {
+        tracing::error!("Failed to parse workflow graph: {}", e);
+        e.to_string()
+    })?;
+    tracing::info!(
+        run_id = emitter.run_id(),
+        "Running workflow with {} nodes and {} edges",
+        graph.nodes.len(),
+        graph.edges.len()
+    );
+
+    if graph.nodes.is_empty() {
+        emitter.log(LogLevel::Info, "[INFO] Workflow is empty. Nothing to run.")?;
+        // Emit the finished event even for an empty workflow
+        emitter.emit(FinishedPayload { success: true })?;
+        return Ok(RunOutcome::Completed);
+    }
+
+    // A resumed run gets its parameters and seed back from the checkpoint.
+    if options.resume_from.is_none() {
+        let parameters = std::mem::take(&mut options.parameters);
+        context.set_parameters(graph.resolve_parameters(parameters)?);
+        context.set_seed(options.seed);
+    }
+    if options.node_timeout.is_none() {
+        options.node_timeout = graph.default_node_timeout();
+    }
+    let graph = WorkflowGraph::build(graph)?;
+
+    // --- Scheduling ---
+    let resume_from = options.resume_from.take();
+    let options = Arc::new(options);
+    let mut scheduler = Scheduler::new(&graph, emitter, control, context, options);
+    match resume_from {
+        Some(checkpoint) => {
+            emitter.log(
+                LogLevel::Info,
+                format!(
+                    "[INFO] Resuming from checkpoint; {} node(s) already finished.",
+                    checkpoint.context.results.len()
+                ),
+            )?;
+            scheduler.restore(checkpoint);
+        }
+        None => {
+            for node in graph.start_nodes() {
+                scheduler.spawn_forward(&node.id);
+            }
+        }
+    }
+
+    let mut pause_changes = control.paused.subscribe();
+    loop {
+        tokio::select! {
+            joined = scheduler.running.join_next() => {
+                let Some(joined) = joined else { break };
+                let (node_id, result, elapsed) = joined.map_err(|e| e.to_string())?;
+                scheduler.in_flight.remove(&node_id);
+                let _busy = scheduler.busy.remove(&node_id);
+                let Some(result) = result else { continue };
+                metrics.node_executed(elapsed);
+                if control.cancel.is_cancelled() {
+                    // Stop scheduling; queued nodes see the token and bail out.
+                    // Errors here are usually nodes interrupted by the cancel.
+                    continue;
+                }
+                let (ran, branch) = match result {
+                    Ok(branch) => (true, branch),
+                    // Returning drops the scheduler, which aborts any branches still in flight.
+                    Err(error) => scheduler.recover(&node_id, error)?,
+                };
+                scheduler.settle(node_id, ran, branch)?;
+                scheduler.emit_progress()?;
+                if let Err(e) = runs::save_checkpoint(db, emitter.run_id(), &scheduler.checkpoint()).await {
+                    tracing::warn!("Could not checkpoint workflow run: {}", e);
+                }
+            }
+            Ok(()) = pause_changes.changed() => {
+                if *pause_changes.borrow_and_update() {
+                    let mut pending: Vec<String> = scheduler.in_flight.keys().cloned().collect();
+                    pending.sort_unstable();
+                    *control.paused_at.lock().unwrap() = pending.clone();
+                    emitter.log(LogLevel::Info, "[INFO] Workflow paused.")?;
+                    emitter.emit(PausedPayload { pending_node_ids: pending })?;
+                } else {
+                    emitter.log(LogLevel::Info, "[INFO] Workflow resumed.")?;
+                    emitter.emit(ResumedPayload {})?;
+                }
+            }
+        }
+    }
+
+    if control.cancel.is_cancelled() {
+        emitter.log(LogLevel::Info, "[INFO] Workflow cancelled.")?;
+        emitter.emit(CancelledPayload {})?;
+        emitter.emit(FinishedPayload { success: false })?;
+        return Ok(RunOutcome::Cancelled);
+    }
+
+    emitter.log(LogLevel::Info, "[INFO] Workflow traversal complete.")?;
+
+    // Emit the final "finished" event to signal completion to the frontend.
+    emitter.emit(FinishedPayload { success: true })?;
+
+    Ok(RunOutcome::Completed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{AppEvent, RecordedEvent};
+    use serde_json::json;
+
+    fn graph(value: Value) -> GraphState {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn loop_with_dangling_edge_ignores_missing_target() {
+        let graph = WorkflowGraph::build(graph(json!({
+            "nodes": [
+                { "id": "start", "type": "default", "data": {} },
+                { "id": "loop", "type": "loop", "data": {} },
+                { "id": "body", "type": "default", "data": {} },
+                { "id": "after", "type": "default", "data": {} }
+            ],
+            "edges": [
+                { "id": "e1", "source": "start", "target": "loop" },
+                { "id": "e2", "source": "loop", "target": "body", "sourceHandle": "body" },
+                { "id": "e3", "source": "body", "target": "loop" },
+                { "id": "e4", "source": "body", "target": "ghost" },
+                { "id": "e5", "source": "loop", "target": "after", "sourceHandle": "done" }
+            ]
+        })))
+        .expect("a dangling edge is only a warning");
+
+        assert_eq!(graph.dangling_edges, vec!["e4".to_string()]);
+        assert_eq!(
+            graph.loop_bodies["loop"],
+            HashSet::from(["body".to_string()])
+        );
+        assert!(graph
+            .adj_list
+            .values()
+            .flatten()
+            .all(|edge| graph.node_map.contains_key(&edge.target)));
+        assert!(!graph.in_degrees().contains_key("ghost"));
+        assert!(!graph.predecessors.contains_key("ghost"));
+    }
+
+    fn order(value: Value) -> Result<Vec<String>, Vec<String>> {
+        let graph = WorkflowGraph::analyze(graph(value));
+        topological_order(&graph.nodes, &graph.adj_list)
+    }
+
+    #[test]
+    fn diamond_runs_the_join_after_both_branches() {
+        let order = order(json!({
+            "nodes": [
+                { "id": "join", "type": "default", "data": {} },
+                { "id": "left", "type": "default", "data": {} },
+                { "id": "right", "type": "default", "data": {} },
+                { "id": "start", "type": "default", "data": {} }
+            ],
+            "edges": [
+                { "id": "e1", "source": "start", "target": "left" },
+                { "id": "e2", "source": "start", "target": "right" },
+                { "id": "e3", "source": "left", "target": "join" },
+                { "id": "e4", "source": "right", "target": "join" }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(order, vec!["start", "left", "right", "join"]);
+    }
+
+    #[test]
+    fn independent_roots_keep_declaration_order() {
+        let order = order(json!({
+            "nodes": [
+                { "id": "b", "type": "default", "data": {} },
+                { "id": "a", "type": "default", "data": {} }
+            ],
+            "edges": []
+        }))
+        .unwrap();
+
+        assert_eq!(order, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn loop_back_edges_are_not_cycles() {
+        let order = order(json!({
+            "nodes": [
+                { "id": "start", "type": "default", "data": {} },
+                { "id": "loop", "type": "loop", "data": {} },
+                { "id": "body", "type": "default", "data": {} },
+                { "id": "after", "type": "default", "data": {} }
+            ],
+            "edges": [
+                { "id": "e1", "source": "start", "target": "loop" },
+                { "id": "e2", "source": "loop", "target": "body", "sourceHandle": "body" },
+                { "id": "e3", "source": "body", "target": "loop" },
+                { "id": "e4", "source": "loop", "target": "after", "sourceHandle": "done" }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(order.first().map(String::as_str), Some("start"));
+        let position = |id: &str| order.iter().position(|n| n == id).unwrap();
+        assert!(position("loop") < position("body"));
+        assert!(position("loop") < position("after"));
+    }
+
+    #[test]
+    fn other_cycles_list_the_blocked_nodes() {
+        let blocked = order(json!({
+            "nodes": [
+                { "id": "start", "type": "default", "data": {} },
+                { "id": "b", "type": "default", "data": {} },
+                { "id": "a", "type": "default", "data": {} }
+            ],
+            "edges": [
+                { "id": "e1", "source": "start", "target": "a" },
+                { "id": "e2", "source": "a", "target": "b" },
+                { "id": "e3", "source": "b", "target": "a" }
+            ]
+        }))
+        .unwrap_err();
+
+        assert_eq!(blocked, vec!["a", "b"]);
+    }
+
+    /// Runs `graph` against an in-memory database and returns how the run
+    /// ended, its context and every event it emitted.
+    fn run(
+        graph: Value,
+        options: ExecutionOptions,
+    ) -> (
+        Result<RunOutcome, String>,
+        ExecutionContext,
+        Vec<RecordedEvent>,
+    ) {
+        let (emitter, events) = RunEmitter::recorded();
+        let context = ExecutionContext::default();
+        let outcome = tauri::async_runtime::block_on(async {
+            let db = Db::in_memory().await.unwrap();
+            execute_workflow(
+                &emitter,
+                &AppMetrics::default(),
+                &db,
+                &RunControl::new(),
+                &context,
+                graph.to_string(),
+                options,
+            )
+            .await
+        });
+        let events = events.lock().unwrap().clone();
+        (outcome, context, events)
+    }
+
+    /// Ids of the nodes named by events called `name`, sorted.
+    fn event_nodes(events: &[RecordedEvent], name: &str) -> Vec<String> {
+        let mut ids: Vec<String> = events
+            .iter()
+            .filter(|event| event.name == name)
+            .filter_map(|event| event.envelope["node_id"].as_str().map(str::to_string))
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn untaken_condition_branch_is_skipped_downstream() {
+        let (outcome, context, events) = run(
+            json!({
+                "nodes": [
+                    { "id": "start", "type": "default", "data": { "output": { "score": 1 } } },
+                    { "id": "check", "type": "condition", "data": { "expression": "input.score > 5" } },
+                    { "id": "high", "type": "default", "data": {} },
+                    { "id": "after_high", "type": "default", "data": {} },
+                    { "id": "low", "type": "default", "data": {} },
+                    { "id": "end", "type": "default", "data": {} }
+                ],
+                "edges": [
+                    { "id": "e1", "source": "start", "target": "check" },
+                    { "id": "e2", "source": "check", "target": "high", "sourceHandle": "true" },
+                    { "id": "e3", "source": "high", "target": "after_high" },
+                    { "id": "e4", "source": "check", "target": "low", "sourceHandle": "false" },
+                    { "id": "e5", "source": "after_high", "target": "end" },
+                    { "id": "e6", "source": "low", "target": "end" }
+                ]
+            }),
+            ExecutionOptions::default(),
+        );
+
+        assert_eq!(outcome, Ok(RunOutcome::Completed));
+        assert_eq!(
+            event_nodes(&events, NodeSkippedPayload::NAME),
+            vec!["after_high", "high"]
+        );
+        assert_eq!(
+            event_nodes(&events, NodeSucceededPayload::NAME),
+            vec!["check", "end", "low", "start"]
+        );
+        assert_eq!(context.output("end"), Some(json!({ "score": 1 })));
+    }
+
+    #[test]
+    fn loop_body_runs_again_on_every_iteration() {
+        let (outcome, context, events) = run(
+            json!({
+                "nodes": [
+                    { "id": "start", "type": "default", "data": {} },
+                    { "id": "loop", "type": "loop", "data": { "max_iterations": 3 } },
+                    { "id": "a", "type": "default", "data": {} },
+                    { "id": "b", "type": "default", "data": {} },
+                    { "id": "after", "type": "default", "data": {} }
+                ],
+                "edges": [
+                    { "id": "e1", "source": "start", "target": "loop" },
+                    { "id": "e2", "source": "loop", "target": "a", "sourceHandle": "body" },
+                    { "id": "e3", "source": "a", "target": "b" },
+                    { "id": "e4", "source": "b", "target": "loop" },
+                    { "id": "e5", "source": "loop", "target": "after", "sourceHandle": "done" }
+                ]
+            }),
+            ExecutionOptions::default(),
+        );
+
+        assert_eq!(outcome, Ok(RunOutcome::Completed));
+        let started = event_nodes(&events, NodeStartedPayload::NAME);
+        let runs = |id: &str| started.iter().filter(|started| *started == id).count();
+        assert_eq!(
+            (runs("loop"), runs("a"), runs("b"), runs("after")),
+            (4, 3, 3, 1)
+        );
+        assert_eq!(context.iteration("loop"), 3);
+    }
+
+    fn map_graph(items: Value) -> Value {
+        json!({
+            "nodes": [
+                { "id": "start", "type": "default", "data": { "output": items } },
+                { "id": "each", "type": "map", "data": { "concurrency": 2 } },
+                { "id": "wrap", "type": "default", "data": { "output": { "value": "{{nodes.each.output}}" } } },
+                { "id": "after", "type": "default", "data": {} }
+            ],
+            "edges": [
+                { "id": "e1", "source": "start", "target": "each" },
+                { "id": "e2", "source": "each", "target": "wrap", "sourceHandle": "body" },
+                { "id": "e3", "source": "each", "target": "after", "sourceHandle": "done" }
+            ]
+        })
+    }
+
+    #[test]
+    fn map_runs_its_body_per_item_in_item_order() {
+        let (outcome, context, events) =
+            run(map_graph(json!([1, 2, 3])), ExecutionOptions::default());
+
+        assert_eq!(outcome, Ok(RunOutcome::Completed));
+        let results = json!([{ "value": 1 }, { "value": 2 }, { "value": 3 }]);
+        assert_eq!(context.output("each"), Some(results.clone()));
+        assert_eq!(context.output("after"), Some(results));
+        let started = event_nodes(&events, NodeStartedPayload::NAME);
+        assert_eq!(started.iter().filter(|id| *id == "wrap").count(), 3);
+    }
+
+    #[test]
+    fn map_over_something_other_than_a_list_fails() {
+        let (outcome, _, _) = run(map_graph(json!({ "x": 1 })), ExecutionOptions::default());
+
+        assert!(outcome.unwrap_err().contains("needs a list"));
+    }
+
+    /// Runs a join node with `data` over `inputs`, keyed by upstream node id.
+    fn join(data: Value, inputs: Value) -> Result<Value, String> {
+        let (emitter, _) = RunEmitter::recorded();
+        let node = Node {
+            id: "join".to_string(),
+            node_type: JOIN_NODE.to_string(),
+            data,
+        };
+        let inputs = serde_json::from_value(inputs).unwrap();
+        tauri::async_runtime::block_on(join_inputs(
+            &emitter,
+            &ExecutionContext::default(),
+            &node,
+            inputs,
+        ))
+        .map(|(output, _)| output)
+    }
+
+    #[test]
+    fn concat_joins_text_in_node_id_order() {
+        let inputs = json!({ "b": "second", "a": "first" });
+        assert_eq!(
+            join(json!({}), inputs.clone()),
+            Ok(json!("first\n\nsecond"))
+        );
+        assert_eq!(
+            join(json!({ "strategy": "concat", "separator": ", " }), inputs),
+            Ok(json!("first, second"))
+        );
+    }
+
+    #[test]
+    fn concat_flattens_other_outputs_into_one_list() {
+        assert_eq!(
+            join(
+                json!({}),
+                json!({ "a": [1, 2], "b": { "x": 1 }, "c": "text" })
+            ),
+            Ok(json!([1, 2, { "x": 1 }, "text"]))
+        );
+    }
+
+    #[test]
+    fn merge_combines_objects_and_rejects_anything_else() {
+        let merge = json!({ "strategy": "merge" });
+        assert_eq!(
+            join(
+                merge.clone(),
+                json!({ "a": { "meta": { "x": 1, "y": 1 } }, "b": { "meta": { "y": 2 }, "z": 3 } })
+            ),
+            Ok(json!({ "meta": { "x": 1, "y": 2 }, "z": 3 }))
+        );
+        assert!(join(merge, json!({ "a": { "x": 1 }, "b": "text" })).is_err());
+        assert!(join(json!({ "strategy": "vote" }), json!({ "a": 1 })).is_err());
+    }
+
+    #[test]
+    fn merge_json_recurses_into_objects_and_replaces_the_rest() {
+        let mut target =
+            json!({ "keep": 1, "nested": { "a": 1, "list": [1, 2] }, "scalar": { "b": 1 } });
+        merge_json(
+            &mut target,
+            json!({ "nested": { "b": 2, "list": [3] }, "scalar": 5, "new": true }),
+        );
+        assert_eq!(
+            target,
+            json!({ "keep": 1, "nested": { "a": 1, "b": 2, "list": [3] }, "scalar": 5, "new": true })
+        );
+    }
+
+    /// `bad` fails because it references a node that never ran.
+    fn failing_graph(policy: &str) -> Value {
+        json!({
+            "errorPolicy": policy,
+            "nodes": [
+                { "id": "start", "type": "default", "data": {} },
+                { "id": "bad", "type": "default", "data": { "prompt": "{{nodes.ghost.output}}" } },
+                { "id": "next", "type": "default", "data": {} },
+                { "id": "handler", "type": "default", "data": {} }
+            ],
+            "edges": [
+                { "id": "e1", "source": "start", "target": "bad" },
+                { "id": "e2", "source": "bad", "target": "next" },
+                { "id": "e3", "source": "bad", "target": "handler", "onError": true }
+            ]
+        })
+    }
+
+    #[test]
+    fn abort_fails_the_run_without_the_error_handler() {
+        let (outcome, _, events) = run(failing_graph("abort"), ExecutionOptions::default());
+
+        assert!(outcome.unwrap_err().contains("ghost"));
+        assert_eq!(
+            event_nodes(&events, NodeSucceededPayload::NAME),
+            vec!["start"]
+        );
+        assert_eq!(event_nodes(&events, NodeFailedPayload::NAME), vec!["bad"]);
+    }
+
+    #[test]
+    fn continue_skips_everything_after_the_failed_node() {
+        let (outcome, _, events) = run(failing_graph("continue"), ExecutionOptions::default());
+
+        assert_eq!(outcome, Ok(RunOutcome::Completed));
+        assert_eq!(
+            event_nodes(&events, NodeSucceededPayload::NAME),
+            vec!["start"]
+        );
+        assert_eq!(
+            event_nodes(&events, NodeSkippedPayload::NAME),
+            vec!["handler", "next"]
+        );
+    }
+
+    #[test]
+    fn route_to_error_handler_hands_it_the_error() {
+        let (outcome, context, events) = run(
+            failing_graph("route-to-error-handler"),
+            ExecutionOptions::default(),
+        );
+
+        assert_eq!(outcome, Ok(RunOutcome::Completed));
+        assert_eq!(
+            event_nodes(&events, NodeSucceededPayload::NAME),
+            vec!["handler", "start"]
+        );
+        assert_eq!(event_nodes(&events, NodeSkippedPayload::NAME), vec!["next"]);
+        let handled = context.output("handler").unwrap();
+        assert!(handled["error"].as_str().unwrap().contains("ghost"));
+    }
+
+    #[test]
+    fn resumed_run_continues_from_its_checkpoint() {
+        let graph = json!({
+            "nodes": [
+                { "id": "start", "type": "default", "data": { "output": "first" } },
+                { "id": "a", "type": "default", "data": {} },
+                { "id": "b", "type": "default", "data": {} }
+            ],
+            "edges": [
+                { "id": "e1", "source": "start", "target": "a" },
+                { "id": "e2", "source": "a", "target": "b" }
+            ]
+        })
+        .to_string();
+        let metrics = AppMetrics::default();
+
+        tauri::async_runtime::block_on(async {
+            let db = Db::in_memory().await.unwrap();
+            let (emitter, _) = RunEmitter::recorded();
+            let run_id = emitter.run_id().to_string();
+            let options = ExecutionOptions {
+                breakpoints: HashSet::from(["b".to_string()]),
+                ..ExecutionOptions::default()
+            };
+            runs::insert_run(&db, &run_id, None, &graph, &options)
+                .await
+                .unwrap();
+
+            // Hold the run at `b`, then stop it once the checkpoint shows
+            // `b` still to run, as if the app had exited there.
+            let control = RunControl::new();
+            let context = ExecutionContext::default();
+            let first = execute_workflow(
+                &emitter,
+                &metrics,
+                &db,
+                &control,
+                &context,
+                graph.clone(),
+                options,
+            );
+            let stop = async {
+                tokio::time::timeout(Duration::from_secs(5), async {
+                    loop {
+                        let saved = runs::load_interrupted_run(&db, &run_id).await.unwrap();
+                        let held = saved
+                            .options
+                            .resume_from
+                            .map_or(false, |checkpoint| checkpoint.pending.contains_key("b"));
+                        if held {
+                            break;
+                        }
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                })
+                .await
+                .expect("the run never reached b");
+                control.cancel.cancel();
+            };
+            let (outcome, ()) = tokio::join!(first, stop);
+            assert_eq!(outcome, Ok(RunOutcome::Cancelled));
+
+            let saved = runs::load_interrupted_run(&db, &run_id).await.unwrap();
+            let (emitter, events) = RunEmitter::recorded();
+            let context = ExecutionContext::default();
+            let options = ExecutionOptions {
+                breakpoints: HashSet::new(),
+                ..saved.options
+            };
+            let outcome = execute_workflow(
+                &emitter,
+                &metrics,
+                &db,
+                &RunControl::new(),
+                &context,
+                saved.graph_state_json,
+                options,
+            )
+            .await;
+
+            assert_eq!(outcome, Ok(RunOutcome::Completed));
+            let events = events.lock().unwrap().clone();
+            assert_eq!(event_nodes(&events, NodeStartedPayload::NAME), vec!["b"]);
+            assert_eq!(context.output("b"), Some(json!("first")));
+            let mut finished: Vec<String> = context.results().into_keys().collect();
+            finished.sort_unstable();
+            assert_eq!(finished, vec!["a", "b", "start"]);
+        });
+    }
+}
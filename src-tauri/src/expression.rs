@@ -0,0 +1,375 @@
+//! A small expression language for `condition` nodes.
+//!
+//! ```text
+//! input.status == 'approved' && input.score >= 0.8
+//! !(nodes.review.output.issues contains 'blocker')
+//! ```
+//!
+//! Paths start at `input` (the condition node's upstream output) or at
+//! `nodes.<id>.output`, and walk into the value with dotted segments; numeric
+//! segments index arrays and missing paths evaluate to `null`. Literals are
+//! numbers, `'single'`/`"double"` quoted strings, `true`, `false`, and `null`.
+//! Operators, loosest first: `||`, `&&`, `!`, then the comparisons `==`,
+//! `!=`, `>`, `>=`, `<`, `<=`, and `contains`.
+
+use crate::context::{select_path, split_output_reference};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Path(String),
+    Literal(Value),
+    Op(&'static str),
+    Not,
+    And,
+    Or,
+    LParen,
+    RParen,
+}
+
+const COMPARISONS: [&str; 6] = ["==", "!=", ">=", "<=", ">", "<"];
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..].iter().take(2).collect();
+        if c.is_whitespace() {
+            i += 1;
+        } else if rest == "&&" {
+            tokens.push(Token::And);
+            i += 2;
+        } else if rest == "||" {
+            tokens.push(Token::Or);
+            i += 2;
+        } else if let Some(op) = COMPARISONS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(*op));
+            i += op.len();
+        } else if c == '!' {
+            tokens.push(Token::Not);
+            i += 1;
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|ch| *ch == c)
+                .ok_or_else(|| format!("Unterminated string starting at position {}.", i))?;
+            let text: String = chars[i + 1..i + 1 + end].iter().collect();
+            tokens.push(Token::Literal(Value::String(text)));
+            i += end + 2;
+        } else if c.is_ascii_digit()
+            || (c == '-' && chars.get(i + 1).map_or(false, char::is_ascii_digit))
+        {
+            let len = chars[i + 1..]
+                .iter()
+                .take_while(|ch| ch.is_ascii_digit() || **ch == '.')
+                .count()
+                + 1;
+            let text: String = chars[i..i + len].iter().collect();
+            let number: f64 = text
+                .parse()
+                .map_err(|_| format!("Invalid number '{}'.", text))?;
+            tokens.push(Token::Literal(serde_json::json!(number)));
+            i += len;
+        } else if c.is_alphanumeric() || c == '_' {
+            let len = chars[i..]
+                .iter()
+                .take_while(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '-' | '.'))
+                .count();
+            let word: String = chars[i..i + len].iter().collect();
+            tokens.push(match word.as_str() {
+                "true" => Token::Literal(Value::Bool(true)),
+                "false" => Token::Literal(Value::Bool(false)),
+                "null" => Token::Literal(Value::Null),
+                "contains" => Token::Op("contains"),
+                _ => Token::Path(word),
+            });
+            i += len;
+        } else {
+            return Err(format!("Unexpected character '{}' at position {}.", c, i));
+        }
+    }
+    Ok(tokens)
+}
+
+/// What paths in an expression can refer to.
+pub struct Scope<'a> {
+    pub input: &'a Value,
+    pub node_output: &'a dyn Fn(&str) -> Option<Value>,
+}
+
+impl Scope<'_> {
+    fn lookup(&self, path: &str) -> Result<Value, String> {
+        if path == "input" {
+            return Ok(self.input.clone());
+        }
+        if let Some(rest) = path.strip_prefix("input.") {
+            return Ok(select_path(self.input, rest).unwrap_or(Value::Null));
+        }
+        if let Some(reference) = path.strip_prefix("nodes.") {
+            if let Some((node_id, rest)) = split_output_reference(reference) {
+                return Ok((self.node_output)(node_id)
+                    .and_then(|output| select_path(&output, rest))
+                    .unwrap_or(Value::Null));
+            }
+        }
+        Err(format!(
+            "Unknown name '{}'; paths must start with 'input' or 'nodes.<id>.output'.",
+            path
+        ))
+    }
+}
+
+struct Parser<'a, 'b> {
+    tokens: Vec<Token>,
+    pos: usize,
+    scope: &'a Scope<'b>,
+}
+
+impl Parser<'_, '_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Value, String> {
+        let mut value = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let rhs = self.and()?;
+            value = Value::Bool(truthy(&value) || truthy(&rhs));
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<Value, String> {
+        let mut value = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            let rhs = self.not()?;
+            value = Value::Bool(truthy(&value) && truthy(&rhs));
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> Result<Value, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Value::Bool(!truthy(&self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Value, String> {
+        let lhs = self.primary()?;
+        let Some(Token::Op(op)) = self.peek().cloned() else {
+            return Ok(lhs);
+        };
+        self.pos += 1;
+        let rhs = self.primary()?;
+        let result = match op {
+            "==" => loosely_equal(&lhs, &rhs),
+            "!=" => !loosely_equal(&lhs, &rhs),
+            "contains" => contains(&lhs, &rhs),
+            _ => {
+                let ordering = compare(&lhs, &rhs);
+                match op {
+                    ">" => ordering == Some(Ordering::Greater),
+                    ">=" => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                    "<" => ordering == Some(Ordering::Less),
+                    _ => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                }
+            }
+        };
+        Ok(Value::Bool(result))
+    }
+
+    fn primary(&mut self) -> Result<Value, String> {
+        match self.advance() {
+            Some(Token::LParen) => {
+                let value = self.or()?;
+                match self.advance() {
+                    Some(Token::RParen) => Ok(value),
+                    _ => Err("Expected ')'.".to_string()),
+                }
+            }
+            Some(Token::Literal(value)) => Ok(value),
+            Some(Token::Path(path)) => self.scope.lookup(&path),
+            Some(other) => Err(format!("Unexpected token {:?}.", other)),
+            None => Err("Unexpected end of expression.".to_string()),
+        }
+    }
+}
+
+/// JSON truthiness: `null`, `false`, `0`, and empty strings, arrays, and
+/// objects are false; everything else is true.
+pub fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map_or(false, |n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+fn loosely_equal(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs.as_f64(), rhs.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => lhs == rhs,
+    }
+}
+
+fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn contains(haystack: &Value, needle: &Value) -> bool {
+    match (haystack, needle) {
+        (Value::String(s), Value::String(sub)) => s.contains(sub.as_str()),
+        (Value::Array(items), _) => items.iter().any(|item| loosely_equal(item, needle)),
+        (Value::Object(map), Value::String(key)) => map.contains_key(key),
+        _ => false,
+    }
+}
+
+/// # evaluate
+/// Parses and evaluates `src` against `scope`, returning the resulting value.
+pub fn evaluate(src: &str, scope: &Scope) -> Result<Value, String> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        pos: 0,
+        scope,
+    };
+    let value = parser.or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!(
+            "Unexpected {:?} after the end of the expression.",
+            parser.tokens[parser.pos]
+        ));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn no_nodes(_: &str) -> Option<Value> {
+        None
+    }
+
+    fn eval(src: &str, input: Value) -> Result<Value, String> {
+        evaluate(
+            src,
+            &Scope {
+                input: &input,
+                node_output: &no_nodes,
+            },
+        )
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(eval("true || false && false", Value::Null), Ok(json!(true)));
+        assert_eq!(
+            eval("(true || false) && false", Value::Null),
+            Ok(json!(false))
+        );
+    }
+
+    #[test]
+    fn not_applies_to_the_whole_comparison() {
+        let input = json!({ "score": 1 });
+        assert_eq!(eval("!input.score == 1", input.clone()), Ok(json!(false)));
+        assert_eq!(eval("!input.score == 2", input), Ok(json!(true)));
+    }
+
+    #[test]
+    fn not_equal_is_not_read_as_not() {
+        let input = json!({ "status": "open" });
+        assert_eq!(
+            eval("input.status != 'done'", input.clone()),
+            Ok(json!(true))
+        );
+        assert_eq!(eval("!(input.status == 'open')", input), Ok(json!(false)));
+    }
+
+    #[test]
+    fn negative_numbers() {
+        let input = json!({ "delta": -2 });
+        assert_eq!(eval("input.delta == -2", input.clone()), Ok(json!(true)));
+        assert_eq!(eval("input.delta < -1.5", input), Ok(json!(true)));
+    }
+
+    #[test]
+    fn unterminated_string_is_an_error() {
+        let err = eval("input.status == 'done", json!({})).unwrap_err();
+        assert!(err.starts_with("Unterminated string"), "{}", err);
+    }
+
+    #[test]
+    fn missing_paths_are_null() {
+        assert_eq!(
+            eval("input.missing.field == null", json!({})),
+            Ok(json!(true))
+        );
+    }
+
+    #[test]
+    fn node_outputs_and_contains() {
+        let outputs = |id: &str| (id == "review").then(|| json!({ "issues": ["nit", "blocker"] }));
+        let scope = Scope {
+            input: &Value::Null,
+            node_output: &outputs,
+        };
+        assert_eq!(
+            evaluate("nodes.review.output.issues contains 'blocker'", &scope),
+            Ok(json!(true))
+        );
+        assert_eq!(
+            evaluate("nodes.other.output.issues contains 'blocker'", &scope),
+            Ok(json!(false))
+        );
+    }
+
+    #[test]
+    fn output_must_be_a_whole_segment() {
+        let outputs = |id: &str| (id == "x.output2").then(|| json!({ "ok": true }));
+        let scope = Scope {
+            input: &Value::Null,
+            node_output: &outputs,
+        };
+        assert_eq!(
+            evaluate("nodes.x.output2.output.ok", &scope),
+            Ok(json!(true))
+        );
+        assert!(evaluate("nodes.x.outputs == 1", &scope).is_err());
+        assert!(evaluate("nodes.x.outputfoo == 1", &scope).is_err());
+    }
+
+    #[test]
+    fn trailing_tokens_are_an_error() {
+        assert!(eval("true false", Value::Null).is_err());
+        assert!(eval("unknown == 1", Value::Null).is_err());
+    }
+}
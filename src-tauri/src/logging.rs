@@ -0,0 +1,298 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::Manager;
+use tracing::Level;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+const LOG_FILE_PREFIX: &str = "squadaid.log";
+const TELEMETRY_SETTINGS_FILE: &str = "telemetry_settings.json";
+
+/// Daily files older than this many days are deleted as new ones are started.
+const MAX_LOG_FILES: usize = 14;
+
+/// Overrides the default filter with `RUST_LOG`-style directives, e.g.
+/// `debug` or `info,sqlx=debug`.
+const LOG_FILTER_ENV: &str = "SQUADAID_LOG";
+
+/// An OTLP collector endpoint (e.g. `http://localhost:4317`) used when none
+/// is configured in the telemetry settings.
+const OTLP_ENDPOINT_ENV: &str = "SQUADAID_OTLP_ENDPOINT";
+
+/// A port for the Prometheus metrics endpoint, used when none is configured
+/// in the telemetry settings.
+const METRICS_PORT_ENV: &str = "SQUADAID_METRICS_PORT";
+
+/// @struct LogState
+/// Managed state holding the log directory and the appender guard. The guard
+/// must live as long as the app, otherwise buffered lines are dropped.
+pub struct LogState {
+    pub dir: PathBuf,
+    _guard: WorkerGuard,
+}
+
+/// @struct TelemetrySettings
+/// Where spans and metrics are exported. Changes take effect on the next
+/// start, since the exporter is installed with the global subscriber and
+/// the metrics listener is bound at startup.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TelemetrySettings {
+    /// An OTLP collector endpoint; workflow and node spans are exported to it
+    /// alongside the file log. `None` keeps spans local.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// Serves the app metrics in Prometheus text format on
+    /// `127.0.0.1:<port>`. `None` keeps the endpoint off.
+    #[serde(default)]
+    pub metrics_port: Option<u16>,
+}
+
+/// @struct TelemetryState
+/// Managed state holding the telemetry settings, persisted as JSON in the
+/// app data directory.
+pub struct TelemetryState {
+    path: PathBuf,
+    settings: Mutex<TelemetrySettings>,
+}
+
+impl TelemetryState {
+    fn load(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(TELEMETRY_SETTINGS_FILE);
+        let settings = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            Err(_) => TelemetrySettings::default(),
+        };
+        Ok(TelemetryState {
+            path,
+            settings: Mutex::new(settings),
+        })
+    }
+
+    fn save(&self, settings: &TelemetrySettings) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+        fs::write(&self.path, json).map_err(|e| e.to_string())
+    }
+
+    /// The configured endpoint, falling back to `SQUADAID_OTLP_ENDPOINT`.
+    fn otlp_endpoint(&self) -> Option<String> {
+        self.settings
+            .lock()
+            .unwrap()
+            .otlp_endpoint
+            .clone()
+            .or_else(|| std::env::var(OTLP_ENDPOINT_ENV).ok())
+            .filter(|endpoint| !endpoint.is_empty())
+    }
+
+    /// The configured metrics port, falling back to `SQUADAID_METRICS_PORT`.
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.settings.lock().unwrap().metrics_port.or_else(|| {
+            std::env::var(METRICS_PORT_ENV)
+                .ok()
+                .and_then(|port| port.parse().ok())
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogEntry {
+    pub timestamp: String,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+/// # init
+/// Installs the global `tracing` subscriber, writing JSON lines to a daily
+/// rotating file under the app data directory, and registers `LogState` and
+/// `TelemetryState`. This crate logs at DEBUG and its dependencies at INFO
+/// unless `SQUADAID_LOG` says otherwise. When an OTLP endpoint is configured,
+/// spans are also exported over OTLP.
+pub fn init(app: &tauri::App) -> Result<(), String> {
+    let data_dir = app
+        .path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| "Could not resolve app data directory".to_string())?;
+    let dir = data_dir.join("logs");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let telemetry = TelemetryState::load(&data_dir)?;
+
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_PREFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(&dir)
+        .map_err(|e| e.to_string())?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let filter = EnvFilter::try_from_env(LOG_FILTER_ENV)
+        .unwrap_or_else(|_| EnvFilter::new(format!("info,{}=debug", env!("CARGO_CRATE_NAME"))));
+
+    let otlp_layer = match telemetry.otlp_endpoint() {
+        Some(endpoint) => {
+            // `setup` runs outside the async runtime, but the batch exporter
+            // spawns its task onto it.
+            let runtime = tauri::async_runtime::handle();
+            let _entered = runtime.inner().enter();
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .install_batch(opentelemetry::runtime::Tokio)
+                .map_err(|e| e.to_string())?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().json().with_writer(writer))
+        .with(otlp_layer)
+        .try_init()
+        .map_err(|e| e.to_string())?;
+
+    app.manage(LogState { dir, _guard: guard });
+    app.manage(telemetry);
+    Ok(())
+}
+
+fn parse_line(line: &str) -> Option<LogEntry> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    Some(LogEntry {
+        timestamp: value["timestamp"].as_str()?.to_string(),
+        level: value["level"].as_str()?.to_string(),
+        target: value["target"].as_str().unwrap_or_default().to_string(),
+        message: value["fields"]["message"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+fn log_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with(LOG_FILE_PREFIX))
+        })
+        .collect();
+    // Rotated files are suffixed with the date, so lexical order is chronological.
+    files.sort();
+    Ok(files)
+}
+
+/// # get_logs
+/// Returns the most recent log entries at or above `level` (default `INFO`),
+/// optionally only those after the RFC 3339 timestamp `since`, newest last.
+/// Files are read from the newest back, stopping once `limit` entries are
+/// found or `since` is passed.
+#[tauri::command]
+pub async fn get_logs(
+    state: tauri::State<'_, LogState>,
+    level: Option<String>,
+    since: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<LogEntry>, String> {
+    let min_level: Level = level
+        .as_deref()
+        .unwrap_or("INFO")
+        .parse()
+        .map_err(|_| format!("Unknown log level: {}", level.unwrap_or_default()))?;
+    let since: Option<DateTime<Utc>> = since
+        .map(|s| {
+            DateTime::parse_from_rfc3339(&s)
+                .map(|dt| dt.with_timezone(&Utc))
+                .map_err(|e| e.to_string())
+        })
+        .transpose()?;
+    let limit = limit.unwrap_or(500);
+
+    let mut entries = Vec::new();
+    'files: for path in log_files(&state.dir)?.into_iter().rev() {
+        let file = fs::File::open(&path).map_err(|e| e.to_string())?;
+        let lines: Vec<String> = BufReader::new(file).lines().map_while(Result::ok).collect();
+        for line in lines.iter().rev() {
+            if entries.len() >= limit {
+                break 'files;
+            }
+            let Some(entry) = parse_line(line) else {
+                continue;
+            };
+            if let Some(since) = &since {
+                match DateTime::parse_from_rfc3339(&entry.timestamp) {
+                    Ok(ts) if ts.with_timezone(&Utc) > *since => {}
+                    // Everything further back is older still.
+                    Ok(_) => break 'files,
+                    Err(_) => continue,
+                }
+            }
+            // `Level` orders by verbosity, so "at least as severe" is `<=`.
+            let severe_enough = entry
+                .level
+                .parse::<Level>()
+                .map_or(false, |l| l <= min_level);
+            if severe_enough {
+                entries.push(entry);
+            }
+        }
+    }
+
+    entries.reverse();
+    Ok(entries)
+}
+
+#[tauri::command]
+pub async fn get_telemetry_settings(
+    state: tauri::State<'_, TelemetryState>,
+) -> Result<TelemetrySettings, String> {
+    Ok(state.settings.lock().unwrap().clone())
+}
+
+/// # set_telemetry_settings
+/// Saves the telemetry settings; they apply from the next start.
+#[tauri::command]
+pub async fn set_telemetry_settings(
+    state: tauri::State<'_, TelemetryState>,
+    settings: TelemetrySettings,
+) -> Result<(), String> {
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+            return Err(format!(
+                "OTLP endpoint '{}' must be an http:// or https:// URL",
+                endpoint
+            ));
+        }
+    }
+    if settings.metrics_port == Some(0) {
+        return Err("The metrics port must be between 1 and 65535".to_string());
+    }
+    let mut current = state.settings.lock().unwrap();
+    state.save(&settings)?;
+    *current = settings;
+    Ok(())
+}
+
+/// # open_log_folder
+/// Opens the log directory in the platform file manager.
+#[tauri::command]
+pub async fn open_log_folder(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, LogState>,
+) -> Result<(), String> {
+    tauri::api::shell::open(&app.shell_scope(), state.dir.to_string_lossy(), None)
+        .map_err(|e| e.to_string())
+}
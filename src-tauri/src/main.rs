@@ -3,46 +3,38 @@
 
 use reqwest;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tauri::Manager;
 use tauri_plugin_sql::{Migration, MigrationKind, TauriSql};
 
-// --- Data Structures ---
-
-#[derive(Deserialize, Debug, Clone)]
-struct Node {
-    id: String,
-    #[serde(rename = "type")]
-    node_type: String,
-    data: Value,
-}
-
-#[derive(Deserialize, Debug)]
-struct Edge {
-    id: String,
-    source: String,
-    target: String,
-}
-
-#[derive(Deserialize, Debug)]
-struct GraphState {
-    nodes: Vec<Node>,
-    edges: Vec<Edge>,
-}
-
-#[derive(Clone, serde::Serialize)]
-struct LogPayload {
-    message: String,
-}
+mod context;
+mod db;
+mod events;
+mod executor;
+mod expression;
+mod logging;
+mod metrics;
+mod notifications;
+mod providers;
+mod queue;
+mod run_logs;
+mod runs;
+mod tray;
+mod trigger;
+mod updates;
+mod validation;
+mod workflows;
+
+use context::ExecutionContext;
+use db::Db;
+use events::{Approval, ApprovalDecision, CancelledPayload, FinishedPayload, LogLevel, RunEmitter};
+use executor::{ExecutionOptions, RunControl, RunOutcome, RunRegistry, DEFAULT_MAX_PARALLELISM};
+use metrics::AppMetrics;
+use notifications::{NotificationEvent, NotificationState};
+use queue::RunQueue;
+use runs::RunStatus;
 
-/// @struct FinishedPayload
-/// The payload for the event indicating the workflow has completed.
-#[derive(Clone, serde::Serialize)]
-struct FinishedPayload {
-    success: bool,
-}
+// --- Data Structures ---
 
 #[derive(Serialize, Deserialize)]
 struct GhDeviceCodeRequest {
@@ -72,126 +64,329 @@ async fn db_init() -> Result<(), String> {
     Ok(())
 }
 
-#[tauri::command]
-async fn save_workflow(graph_state_json: String) -> Result<(), String> {
-    // Workflow saving logic...
-    Ok(())
-}
-
-#[tauri::command]
-async fn load_workflow() -> Result<String, String> {
-    // Workflow loading logic...
-    Ok("".to_string())
-}
-
 /// # run_workflow
 /// Final version of the command. It streams logs and emits a completion event.
+/// With `step_mode`, the run stops before each node the saved workflow has a
+/// breakpoint on (see `set_breakpoint`) until `step_run` is called. A `seed`
+/// is passed to providers that support one and recorded with the run, so
+/// running again with the same seed reproduces it as closely as they allow.
+/// Seeds must fit in a signed 64-bit integer, which is how they are stored.
+/// `node_timeout_secs` overrides the workflow's own `timeoutSecs` default.
+// Command arguments are named fields of the frontend's invoke payload.
+#[allow(clippy::too_many_arguments)]
 #[tauri::command]
+#[tracing::instrument(name = "workflow_run", skip_all)]
 async fn run_workflow(
     window: tauri::Window,
+    registry: tauri::State<'_, RunRegistry>,
+    db: tauri::State<'_, Db>,
     graph_state_json: String,
+    workflow_id: Option<String>,
+    max_parallelism: Option<usize>,
+    node_timeout_secs: Option<f64>,
+    parameters: Option<serde_json::Value>,
+    step_mode: Option<bool>,
+    seed: Option<u64>,
 ) -> Result<(), String> {
-    // --- Setup Phase ---
-    let graph: GraphState =
-        serde_json::from_str(&graph_state_json).map_err(|e| e.to_string())?;
-
-    if graph.nodes.is_empty() {
-        window
-            .emit(
-                "execution-log",
-                LogPayload {
-                    message: "[INFO] Workflow is empty. Nothing to run.".to_string(),
-                },
-            )
-            .map_err(|e| e.to_string())?;
-        // Emit the finished event even for an empty workflow
-        window
-            .emit("execution-finished", FinishedPayload { success: true })
-            .map_err(|e| e.to_string())?;
-        return Ok(());
+    let parameters = match parameters {
+        None | Some(serde_json::Value::Null) => serde_json::Map::new(),
+        Some(serde_json::Value::Object(map)) => map,
+        Some(_) => return Err("Workflow parameters must be a JSON object.".to_string()),
+    };
+    if let Some(seed) = seed.filter(|seed| i64::try_from(*seed).is_err()) {
+        return Err(format!(
+            "Seed {} is too large; use at most {}.",
+            seed,
+            i64::MAX
+        ));
     }
+    let breakpoints = match (&workflow_id, step_mode.unwrap_or(false)) {
+        (Some(workflow_id), true) => workflows::breakpoints(&db, workflow_id).await?,
+        (None, true) => {
+            return Err("Step mode needs a saved workflow to read breakpoints from.".to_string())
+        }
+        (_, false) => Default::default(),
+    };
+    let options = ExecutionOptions {
+        max_parallelism: max_parallelism.unwrap_or(DEFAULT_MAX_PARALLELISM),
+        node_timeout: node_timeout_secs
+            .filter(|secs| *secs > 0.0)
+            .and_then(|secs| Duration::try_from_secs_f64(secs).ok()),
+        parameters,
+        resume_from: None,
+        seed,
+        breakpoints,
+    };
+    let emitter = RunEmitter::new(window.clone());
+    let control = registry.try_register(emitter.run_id())?;
+    drive_run(
+        window,
+        emitter,
+        control,
+        graph_state_json,
+        workflow_id,
+        options,
+    )
+    .await
+}
 
-    let mut adj_list: HashMap<String, Vec<String>> = HashMap::new();
-    let mut node_map: HashMap<String, Node> = HashMap::new();
-    let mut edge_targets: HashSet<String> = HashSet::new();
+/// # resume_run
+/// Continues a run that was interrupted (e.g. by a crash) from its last
+/// checkpoint, under the same run id and options. Nodes that were in flight
+/// when the checkpoint was taken run again; a run with no checkpoint starts
+/// over. `max_parallelism` overrides the run's original limit.
+#[tauri::command]
+#[tracing::instrument(name = "workflow_run", skip_all)]
+async fn resume_run(
+    window: tauri::Window,
+    registry: tauri::State<'_, RunRegistry>,
+    db: tauri::State<'_, Db>,
+    run_id: String,
+    max_parallelism: Option<usize>,
+) -> Result<(), String> {
+    // Claimed before anything is awaited, so two resumes cannot both run it.
+    let control = registry.try_register(&run_id)?;
+    let mut interrupted = match runs::load_interrupted_run(&db, &run_id).await {
+        Ok(interrupted) => interrupted,
+        Err(e) => {
+            registry.remove(&run_id);
+            return Err(e);
+        }
+    };
+    if let Some(max_parallelism) = max_parallelism {
+        interrupted.options.max_parallelism = max_parallelism;
+    }
+    let emitter = RunEmitter::with_run_id(window.clone(), run_id);
+    drive_run(
+        window,
+        emitter,
+        control,
+        interrupted.graph_state_json,
+        interrupted.workflow_id,
+        interrupted.options,
+    )
+    .await
+}
 
-    for node in graph.nodes.iter() {
-        node_map.insert(node.id.clone(), node.clone());
-        adj_list.insert(node.id.clone(), Vec::new());
+/// Runs a workflow to completion and takes care of everything around it:
+/// metrics, the tray, run history, and notifications. The caller registers
+/// the run and hands over its control; it is unregistered here.
+async fn drive_run(
+    window: tauri::Window,
+    emitter: RunEmitter,
+    control: RunControl,
+    graph_state_json: String,
+    workflow_id: Option<String>,
+    options: ExecutionOptions,
+) -> Result<(), String> {
+    let app = window.app_handle();
+    let metrics = app.state::<AppMetrics>();
+    let registry = app.state::<RunRegistry>();
+    let db = app.state::<Db>();
+    let started = Instant::now();
+    // Run history is best-effort: a database problem must not fail the run.
+    let inserted = runs::insert_run(
+        &db,
+        emitter.run_id(),
+        workflow_id.as_deref(),
+        &graph_state_json,
+        &options,
+    )
+    .await;
+    if let Err(e) = inserted {
+        tracing::warn!("Could not record workflow run: {}", e);
     }
-    for edge in graph.edges.iter() {
-        if let Some(successors) = adj_list.get_mut(&edge.source) {
-            successors.push(edge.target.clone());
+    let context = ExecutionContext::default();
+    let queue = app.state::<RunQueue>();
+    let result = match queue.acquire(&emitter, &control, &metrics).await {
+        Ok(true) => {
+            metrics.run_started();
+            tray::refresh(&app);
+            if let Err(e) = runs::mark_running(&db, emitter.run_id()).await {
+                tracing::warn!("Could not record workflow run start: {}", e);
+            }
+            let result = executor::execute_workflow(
+                &emitter,
+                &metrics,
+                &db,
+                &control,
+                &context,
+                graph_state_json,
+                options,
+            )
+            .await;
+            control.slot.release();
+            match &result {
+                Ok(RunOutcome::Cancelled) => metrics.run_cancelled(),
+                _ => metrics.run_finished(result.is_ok()),
+            }
+            result
+        }
+        Ok(false) => {
+            metrics.queued_run_cancelled();
+            cancelled_while_queued(&emitter)
         }
-        edge_targets.insert(edge.target.clone());
+        Err(e) => Err(e),
+    };
+    registry.remove(emitter.run_id());
+    tray::refresh(&app);
+    if let Err(e) = &result {
+        // Best-effort: the run has already failed, and this is only its log.
+        let _ = emitter.log(LogLevel::Error, format!("Workflow failed: {}", e));
     }
-    let start_nodes: Vec<&Node> = graph
-        .nodes
-        .iter()
-        .filter(|node| !edge_targets.contains(&node.id))
-        .collect();
 
-    if start_nodes.len() != 1 {
-        // Return an error, which will be caught by the frontend's `catch` block.
-        // The `finally` block on the frontend will handle UI state.
-        return Err(format!(
-            "Workflow must have exactly one start node (a node with no incoming edges). Found {}.",
-            start_nodes.len()
-        ));
+    let (status, error) = match &result {
+        Ok(RunOutcome::Completed) => (RunStatus::Completed, None),
+        Ok(RunOutcome::Cancelled) => (RunStatus::Cancelled, None),
+        Err(e) => (RunStatus::Failed, Some(e.as_str())),
+    };
+    let finished = runs::finish_run(
+        &db,
+        emitter.run_id(),
+        status,
+        error,
+        &context.results(),
+        &context.usage(),
+    )
+    .await;
+    if let Err(e) = finished {
+        tracing::warn!("Could not record workflow run result: {}", e);
     }
-    let start_node_id = start_nodes[0].id.clone();
 
-    // --- Traversal and Event Emitting ---
-    let mut queue: VecDeque<String> = VecDeque::new();
-    let mut visited: HashSet<String> = HashSet::new();
-    queue.push_back(start_node_id.clone());
-    visited.insert(start_node_id);
-
-    while let Some(node_id) = queue.pop_front() {
-        if let Some(node) = node_map.get(&node_id) {
-            let node_name = node.data["name"].as_str().unwrap_or("Unnamed");
-            let message = format!(
-                "[EXEC] Visiting node '{}' (Type: {})",
-                node_name, node.node_type
+    let desktop = app.state::<NotificationState>().desktop();
+    match &result {
+        Ok(RunOutcome::Cancelled) => {}
+        Ok(RunOutcome::Completed) => {
+            notifications::notify(
+                &app,
+                NotificationEvent::RunFinished,
+                format!("Workflow run {} finished.", emitter.run_id()),
             );
-            window
-                .emit("execution-log", LogPayload { message })
-                .map_err(|e| e.to_string())?;
-
-            // The artificial sleep has been REMOVED.
-            // In a real app, this is where agent logic would run.
-
-            if let Some(successors) = adj_list.get(&node_id) {
-                for successor_id in successors {
-                    if !visited.contains(successor_id) {
-                        visited.insert(successor_id.clone());
-                        queue.push_back(successor_id.clone());
-                    }
-                }
+            if desktop.long_runs && started.elapsed().as_secs() >= desktop.long_run_threshold_secs {
+                notifications::notify_desktop(
+                    &app,
+                    "Workflow finished",
+                    "A long-running workflow has completed.",
+                );
+            }
+        }
+        Err(e) => {
+            notifications::notify(
+                &app,
+                NotificationEvent::RunFailed,
+                format!("Workflow run {} failed: {}", emitter.run_id(), e),
+            );
+            if desktop.run_failures {
+                notifications::notify_desktop(&app, "Workflow failed", e);
             }
         }
     }
+    result.map(|_| ())
+}
 
-    window
-        .emit(
-            "execution-log",
-            LogPayload {
-                message: "[INFO] Workflow traversal complete.".to_string(),
-            },
-        )
-        .map_err(|e| e.to_string())?;
+/// Ends a run that was cancelled before it got a slot, with the same events
+/// a run cancelled mid-execution emits.
+fn cancelled_while_queued(emitter: &RunEmitter) -> Result<RunOutcome, String> {
+    emitter.log(
+        LogLevel::Info,
+        "[INFO] Workflow cancelled before it started.",
+    )?;
+    emitter.emit(CancelledPayload {})?;
+    emitter.emit(FinishedPayload { success: false })?;
+    Ok(RunOutcome::Cancelled)
+}
 
-    // Emit the final "finished" event to signal completion to the frontend.
-    window
-        .emit("execution-finished", FinishedPayload { success: true })
-        .map_err(|e| e.to_string())?;
+/// # cancel_workflow
+/// Requests cooperative cancellation of a running workflow. The run stops
+/// scheduling new nodes and emits `execution-cancelled` once in-flight nodes
+/// have finished.
+#[tauri::command]
+async fn cancel_workflow(
+    registry: tauri::State<'_, RunRegistry>,
+    run_id: String,
+) -> Result<(), String> {
+    let control = registry
+        .get(&run_id)
+        .ok_or_else(|| format!("No running workflow with id '{}'.", run_id))?;
+    control.cancel.cancel();
+    Ok(())
+}
 
+/// # pause_workflow
+/// Parks a running workflow before its next node starts. The executor emits
+/// `execution-paused` with the pending node ids once the pause takes effect.
+#[tauri::command]
+async fn pause_workflow(
+    registry: tauri::State<'_, RunRegistry>,
+    run_id: String,
+) -> Result<(), String> {
+    let control = registry
+        .get(&run_id)
+        .ok_or_else(|| format!("No running workflow with id '{}'.", run_id))?;
+    if !control.pause() {
+        return Err(format!("Workflow run '{}' is already paused.", run_id));
+    }
     Ok(())
 }
 
-#[derive(Clone, serde::Serialize)]
-struct Payload {
-  message: String,
+/// # resume_workflow
+/// Continues a paused workflow from where it was parked and returns the node
+/// ids it was parked at.
+#[tauri::command]
+async fn resume_workflow(
+    registry: tauri::State<'_, RunRegistry>,
+    run_id: String,
+) -> Result<Vec<String>, String> {
+    let control = registry
+        .get(&run_id)
+        .ok_or_else(|| format!("No running workflow with id '{}'.", run_id))?;
+    if !control.resume() {
+        return Err(format!("Workflow run '{}' is not paused.", run_id));
+    }
+    Ok(control.paused_at())
+}
+
+/// # step_run
+/// Lets a run in step mode continue past the breakpoint it has been stopped
+/// at longest, and returns the id of the node that now starts.
+#[tauri::command]
+async fn step_run(
+    registry: tauri::State<'_, RunRegistry>,
+    run_id: String,
+) -> Result<String, String> {
+    let control = registry
+        .get(&run_id)
+        .ok_or_else(|| format!("No running workflow with id '{}'.", run_id))?;
+    control
+        .step()
+        .ok_or_else(|| format!("Workflow run '{}' is not stopped at a breakpoint.", run_id))
+}
+
+/// # approve_node
+/// Answers the approval request of an `approval` node in a running workflow,
+/// letting it continue down its `"approved"` or `"rejected"` branch. Pass the
+/// `interaction_id` from `interaction-requested` when the node sits in a map
+/// body and may be waiting for several items at once.
+#[tauri::command]
+async fn approve_node(
+    app: tauri::AppHandle,
+    registry: tauri::State<'_, RunRegistry>,
+    run_id: String,
+    node_id: String,
+    interaction_id: Option<String>,
+    decision: ApprovalDecision,
+    comment: Option<String>,
+) -> Result<(), String> {
+    let control = registry
+        .get(&run_id)
+        .ok_or_else(|| format!("No running workflow with id '{}'.", run_id))?;
+    control.decide(
+        &node_id,
+        interaction_id.as_deref(),
+        Approval { decision, comment },
+    )?;
+    tray::refresh(&app);
+    Ok(())
 }
 
 #[tauri::command]
@@ -285,9 +480,25 @@ fn main() {
                 kind: MigrationKind::Up,
             }],
         ))
+        .manage(AppMetrics::default())
+        .manage(RunRegistry::default())
+        .system_tray(tray::build())
+        .on_system_tray_event(tray::handle_event)
         .setup(|app| {
+            logging::init(app)?;
+            let db = Db::open(app)?;
+            tauri::async_runtime::block_on(runs::mark_interrupted_runs(&db))?;
+            app.manage(db);
+            app.manage(run_logs::RunLogWriter::spawn(
+                app.state::<Db>().pool.clone(),
+            ));
+            app.manage(RunQueue::load(app)?);
+            metrics::spawn_prometheus_listener(app.handle());
+            trigger::spawn_trigger_listener(app.handle());
+            app.manage(NotificationState::load(app)?);
+            updates::spawn_periodic_check(app.handle());
             app.listen_global("my-event", |event| {
-                println!("Received event: {:?}", event.payload());
+                tracing::debug!("Received event: {:?}", event.payload());
             });
             Ok(())
         })
@@ -298,10 +509,46 @@ fn main() {
             test_ollama_connection,
             greet,
             db_init,
-            save_workflow,
-            load_workflow,
-            run_workflow
+            run_workflow,
+            cancel_workflow,
+            pause_workflow,
+            resume_workflow,
+            resume_run,
+            step_run,
+            approve_node,
+            logging::get_logs,
+            logging::get_telemetry_settings,
+            logging::set_telemetry_settings,
+            logging::open_log_folder,
+            metrics::get_app_metrics,
+            notifications::list_notification_channels,
+            notifications::set_notification_channels,
+            notifications::test_notification_channel,
+            notifications::get_desktop_notification_settings,
+            notifications::set_desktop_notification_settings,
+            queue::get_max_concurrent_runs,
+            queue::set_max_concurrent_runs,
+            run_logs::get_run_logs,
+            runs::get_workflow_runs,
+            runs::get_run_details,
+            runs::get_run_metrics,
+            runs::replay_workflow_run,
+            updates::check_for_updates,
+            validation::validate_workflow,
+            workflows::list_workflows,
+            workflows::save_workflow,
+            workflows::load_workflow,
+            workflows::rename_workflow,
+            workflows::delete_workflow,
+            workflows::get_workflow_versions,
+            workflows::restore_workflow_version,
+            workflows::export_workflow,
+            workflows::import_workflow,
+            workflows::get_workflow_parameters,
+            workflows::set_breakpoint,
+            workflows::clear_breakpoint,
+            workflows::get_breakpoints
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
@@ -0,0 +1,298 @@
+use crate::logging::TelemetryState;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::Manager;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Pause after a failed accept (e.g. out of file descriptors) before trying
+/// again, so the loop does not spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Upper bounds (in milliseconds) of the duration histogram buckets. Anything
+/// slower lands in the implicit overflow bucket.
+const DURATION_BUCKETS_MS: [u64; 8] = [1, 5, 10, 50, 100, 500, 1_000, 5_000];
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum_ms: f64,
+    pub average_ms: f64,
+    /// `(upper_bound_ms, count)` pairs; the last entry (`None`) is the overflow bucket.
+    pub buckets: Vec<(Option<u64>, u64)>,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        let mut buckets: Vec<(Option<u64>, u64)> =
+            DURATION_BUCKETS_MS.iter().map(|b| (Some(*b), 0)).collect();
+        buckets.push((None, 0));
+        Histogram {
+            buckets,
+            ..Default::default()
+        }
+    }
+
+    fn observe(&mut self, duration: Duration) {
+        let ms = duration.as_secs_f64() * 1_000.0;
+        self.count += 1;
+        self.sum_ms += ms;
+        self.average_ms = self.sum_ms / self.count as f64;
+        let slot = self
+            .buckets
+            .iter_mut()
+            .find(|(bound, _)| bound.map_or(true, |b| ms <= b as f64));
+        if let Some((_, count)) = slot {
+            *count += 1;
+        }
+    }
+}
+
+/// @struct MetricsSnapshot
+/// The payload returned by `get_app_metrics`.
+#[derive(Serialize, Debug, Clone)]
+pub struct MetricsSnapshot {
+    pub runs_started: u64,
+    pub runs_succeeded: u64,
+    pub runs_failed: u64,
+    pub runs_cancelled: u64,
+    /// Runs that hold a run slot and are executing.
+    pub active_runs: u64,
+    /// Runs waiting for a free run slot.
+    pub queued_runs: u64,
+    pub provider_calls: HashMap<String, u64>,
+    pub node_duration: Histogram,
+    /// Run history and saved workflow queries made by commands.
+    pub db_query_duration: Histogram,
+}
+
+/// @struct AppMetrics
+/// Process-wide counters and histograms, registered as managed state. All
+/// recording methods are cheap enough to call on every node and request.
+pub struct AppMetrics {
+    inner: Mutex<MetricsSnapshot>,
+}
+
+impl Default for AppMetrics {
+    fn default() -> Self {
+        AppMetrics {
+            inner: Mutex::new(MetricsSnapshot {
+                runs_started: 0,
+                runs_succeeded: 0,
+                runs_failed: 0,
+                runs_cancelled: 0,
+                active_runs: 0,
+                queued_runs: 0,
+                provider_calls: HashMap::new(),
+                node_duration: Histogram::new(),
+                db_query_duration: Histogram::new(),
+            }),
+        }
+    }
+}
+
+impl AppMetrics {
+    pub fn run_queued(&self) {
+        self.inner.lock().unwrap().queued_runs += 1;
+    }
+
+    pub fn run_dequeued(&self) {
+        let mut m = self.inner.lock().unwrap();
+        m.queued_runs = m.queued_runs.saturating_sub(1);
+    }
+
+    /// Counts a run that got a slot and started executing.
+    pub fn run_started(&self) {
+        let mut m = self.inner.lock().unwrap();
+        m.runs_started += 1;
+        m.active_runs += 1;
+    }
+
+    pub fn run_finished(&self, success: bool) {
+        let mut m = self.inner.lock().unwrap();
+        m.active_runs = m.active_runs.saturating_sub(1);
+        if success {
+            m.runs_succeeded += 1;
+        } else {
+            m.runs_failed += 1;
+        }
+    }
+
+    pub fn run_cancelled(&self) {
+        let mut m = self.inner.lock().unwrap();
+        m.active_runs = m.active_runs.saturating_sub(1);
+        m.runs_cancelled += 1;
+    }
+
+    /// Counts a run cancelled before it got a slot, which never became active.
+    pub fn queued_run_cancelled(&self) {
+        self.inner.lock().unwrap().runs_cancelled += 1;
+    }
+
+    pub fn provider_call(&self, provider: &str) {
+        let mut m = self.inner.lock().unwrap();
+        *m.provider_calls.entry(provider.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn node_executed(&self, duration: Duration) {
+        self.inner.lock().unwrap().node_duration.observe(duration);
+    }
+
+    /// Awaits `query` and records how long it took.
+    pub async fn db_query<F: Future>(&self, query: F) -> F::Output {
+        let started = Instant::now();
+        let output = query.await;
+        self.inner
+            .lock()
+            .unwrap()
+            .db_query_duration
+            .observe(started.elapsed());
+        output
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.inner.lock().unwrap().clone()
+    }
+}
+
+/// # get_app_metrics
+/// Returns a point-in-time copy of all collected metrics for the health dashboard.
+#[tauri::command]
+pub async fn get_app_metrics(
+    metrics: tauri::State<'_, AppMetrics>,
+) -> Result<MetricsSnapshot, String> {
+    Ok(metrics.snapshot())
+}
+
+/// Renders a snapshot in the Prometheus text exposition format.
+pub fn render_prometheus(m: &MetricsSnapshot) -> String {
+    let mut out = String::new();
+    let counters = [
+        (
+            "squadaid_runs_started_total",
+            "Workflow runs started.",
+            m.runs_started,
+        ),
+        (
+            "squadaid_runs_succeeded_total",
+            "Workflow runs that succeeded.",
+            m.runs_succeeded,
+        ),
+        (
+            "squadaid_runs_failed_total",
+            "Workflow runs that failed.",
+            m.runs_failed,
+        ),
+        (
+            "squadaid_runs_cancelled_total",
+            "Workflow runs that were cancelled.",
+            m.runs_cancelled,
+        ),
+    ];
+    for (name, help, value) in counters {
+        let _ = writeln!(
+            out,
+            "# HELP {} {}\n# TYPE {} counter\n{} {}",
+            name, help, name, name, value
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP squadaid_active_runs Workflow runs currently executing.\n# TYPE squadaid_active_runs gauge\nsquadaid_active_runs {}",
+        m.active_runs
+    );
+    let _ = writeln!(
+        out,
+        "# HELP squadaid_queued_runs Workflow runs waiting for a free run slot.\n# TYPE squadaid_queued_runs gauge\nsquadaid_queued_runs {}",
+        m.queued_runs
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP squadaid_provider_calls_total Provider requests by provider.\n# TYPE squadaid_provider_calls_total counter"
+    );
+    let mut providers: Vec<_> = m.provider_calls.iter().collect();
+    providers.sort();
+    for (provider, count) in providers {
+        let _ = writeln!(
+            out,
+            "squadaid_provider_calls_total{{provider=\"{}\"}} {}",
+            provider, count
+        );
+    }
+
+    write_histogram(
+        &mut out,
+        "squadaid_node_duration_ms",
+        "Node execution time in milliseconds.",
+        &m.node_duration,
+    );
+    write_histogram(
+        &mut out,
+        "squadaid_db_query_duration_ms",
+        "Run history and workflow query time in milliseconds.",
+        &m.db_query_duration,
+    );
+    out
+}
+
+fn write_histogram(out: &mut String, name: &str, help: &str, h: &Histogram) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+    let mut cumulative = 0;
+    for (bound, count) in &h.buckets {
+        cumulative += count;
+        let le = bound.map_or("+Inf".to_string(), |b| b.to_string());
+        let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+    }
+    let _ = writeln!(out, "{}_sum {}", name, h.sum_ms);
+    let _ = writeln!(out, "{}_count {}", name, h.count);
+}
+
+/// # spawn_prometheus_listener
+/// Starts the localhost metrics endpoint if the telemetry settings (or
+/// `SQUADAID_METRICS_PORT`) give it a port. Every request is answered with
+/// the current metrics regardless of path.
+pub fn spawn_prometheus_listener(app: tauri::AppHandle) {
+    let Some(port) = app.state::<TelemetryState>().metrics_port() else {
+        return;
+    };
+
+    tauri::async_runtime::spawn(async move {
+        let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!("Could not bind metrics listener on port {}: {}", port, e);
+                return;
+            }
+        };
+        tracing::info!("Serving Prometheus metrics on 127.0.0.1:{}", port);
+
+        loop {
+            let mut socket = match listener.accept().await {
+                Ok((socket, _)) => socket,
+                Err(e) => {
+                    tracing::warn!("Could not accept metrics connection: {}", e);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            let body = render_prometheus(&app.state::<AppMetrics>().snapshot());
+            tauri::async_runtime::spawn(async move {
+                // The request itself is irrelevant; read it so the client sees a clean response.
+                let mut buf = [0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+}
@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::Manager;
+
+const SETTINGS_FILE: &str = "notification_settings.json";
+
+/// The occurrences a channel can subscribe to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEvent {
+    RunFinished,
+    RunFailed,
+    /// A run reached an `approval` node and waits for a decision.
+    ApprovalNeeded,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelKind {
+    Slack,
+    Discord,
+}
+
+/// @struct NotificationChannel
+/// An incoming-webhook destination and the events it wants to hear about.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NotificationChannel {
+    pub id: String,
+    pub kind: ChannelKind,
+    /// A secret in its own right, but stored in plaintext in the settings
+    /// file until the app has a keyring to keep it in.
+    pub webhook_url: String,
+    pub events: Vec<NotificationEvent>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// @struct DesktopNotificationSettings
+/// Per-category toggles for OS-level notifications.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DesktopNotificationSettings {
+    #[serde(default = "default_enabled")]
+    pub approval_requests: bool,
+    pub run_failures: bool,
+    pub long_runs: bool,
+    /// Runs taking at least this long count as "long" when they complete.
+    pub long_run_threshold_secs: u64,
+}
+
+impl Default for DesktopNotificationSettings {
+    fn default() -> Self {
+        DesktopNotificationSettings {
+            approval_requests: true,
+            run_failures: true,
+            long_runs: true,
+            long_run_threshold_secs: 60,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct NotificationSettings {
+    #[serde(default)]
+    channels: Vec<NotificationChannel>,
+    #[serde(default)]
+    desktop: DesktopNotificationSettings,
+}
+
+/// @struct NotificationState
+/// Managed state holding the webhook channels and desktop toggles, persisted
+/// as JSON in the app data directory.
+pub struct NotificationState {
+    path: PathBuf,
+    identifier: String,
+    settings: Mutex<NotificationSettings>,
+}
+
+impl NotificationState {
+    pub fn load(app: &tauri::App) -> Result<Self, String> {
+        let dir = app
+            .path_resolver()
+            .app_data_dir()
+            .ok_or_else(|| "Could not resolve app data directory".to_string())?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(SETTINGS_FILE);
+        let settings = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            Err(_) => NotificationSettings::default(),
+        };
+        Ok(NotificationState {
+            path,
+            identifier: app.config().tauri.bundle.identifier.clone(),
+            settings: Mutex::new(settings),
+        })
+    }
+
+    fn save(&self, settings: &NotificationSettings) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+        fs::write(&self.path, json).map_err(|e| e.to_string())
+    }
+
+    pub fn desktop(&self) -> DesktopNotificationSettings {
+        self.settings.lock().unwrap().desktop.clone()
+    }
+}
+
+fn webhook_body(kind: ChannelKind, text: &str) -> serde_json::Value {
+    match kind {
+        ChannelKind::Slack => serde_json::json!({ "text": text }),
+        ChannelKind::Discord => serde_json::json!({ "content": text }),
+    }
+}
+
+async fn post(channel: &NotificationChannel, text: &str) -> Result<(), String> {
+    let client = reqwest::Client::new();
+    let res = client
+        .post(&channel.webhook_url)
+        .header("User-Agent", "SquadAID-Tauri-App")
+        .json(&webhook_body(channel.kind, text))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if res.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("Webhook failed with status: {}", res.status()))
+    }
+}
+
+/// # notify
+/// Posts `text` to every enabled channel subscribed to `event`. Delivery runs
+/// in the background and failures are only logged, so callers never wait on
+/// or fail because of a webhook.
+pub fn notify(app: &tauri::AppHandle, event: NotificationEvent, text: String) {
+    let channels: Vec<NotificationChannel> = app
+        .state::<NotificationState>()
+        .settings
+        .lock()
+        .unwrap()
+        .channels
+        .iter()
+        .filter(|c| c.enabled && c.events.contains(&event))
+        .cloned()
+        .collect();
+
+    for channel in channels {
+        let text = text.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = post(&channel, &text).await {
+                tracing::warn!("Notification to channel '{}' failed: {}", channel.id, e);
+            }
+        });
+    }
+}
+
+/// # approval_needed
+/// Tells the channels subscribed to `ApprovalNeeded` that a run is waiting
+/// at an approval node, and shows a desktop notification if that is on.
+pub fn approval_needed(app: &tauri::AppHandle, run_id: &str, node_id: &str, prompt: &str) {
+    if app.state::<NotificationState>().desktop().approval_requests {
+        notify_desktop(app, "Approval needed", prompt);
+    }
+    notify(
+        app,
+        NotificationEvent::ApprovalNeeded,
+        format!(
+            "Workflow run {} is waiting for approval at node '{}': {}",
+            run_id, node_id, prompt
+        ),
+    );
+}
+
+/// # notify_desktop
+/// Shows an OS-level notification. Callers check the relevant toggle in
+/// `NotificationState::desktop` first.
+pub fn notify_desktop(app: &tauri::AppHandle, title: &str, body: &str) {
+    let identifier = app.state::<NotificationState>().identifier.clone();
+    if let Err(e) = tauri::api::notification::Notification::new(identifier)
+        .title(title)
+        .body(body)
+        .show()
+    {
+        tracing::warn!("Desktop notification failed: {}", e);
+    }
+}
+
+#[tauri::command]
+pub async fn list_notification_channels(
+    state: tauri::State<'_, NotificationState>,
+) -> Result<Vec<NotificationChannel>, String> {
+    Ok(state.settings.lock().unwrap().channels.clone())
+}
+
+/// # set_notification_channels
+/// Replaces the channel list and writes it to disk.
+#[tauri::command]
+pub async fn set_notification_channels(
+    state: tauri::State<'_, NotificationState>,
+    channels: Vec<NotificationChannel>,
+) -> Result<(), String> {
+    if let Some(bad) = channels
+        .iter()
+        .find(|c| !c.webhook_url.starts_with("https://"))
+    {
+        return Err(format!(
+            "Channel '{}' must use an https:// webhook URL",
+            bad.id
+        ));
+    }
+    let mut settings = state.settings.lock().unwrap();
+    let updated = NotificationSettings {
+        channels,
+        ..settings.clone()
+    };
+    state.save(&updated)?;
+    *settings = updated;
+    Ok(())
+}
+
+/// # test_notification_channel
+/// Sends a test message synchronously so the settings UI can report failures.
+#[tauri::command]
+pub async fn test_notification_channel(channel: NotificationChannel) -> Result<(), String> {
+    post(
+        &channel,
+        "SquadAID test notification: this channel is configured correctly.",
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn get_desktop_notification_settings(
+    state: tauri::State<'_, NotificationState>,
+) -> Result<DesktopNotificationSettings, String> {
+    Ok(state.desktop())
+}
+
+#[tauri::command]
+pub async fn set_desktop_notification_settings(
+    state: tauri::State<'_, NotificationState>,
+    desktop: DesktopNotificationSettings,
+) -> Result<(), String> {
+    let mut settings = state.settings.lock().unwrap();
+    let updated = NotificationSettings {
+        desktop,
+        ..settings.clone()
+    };
+    state.save(&updated)?;
+    *settings = updated;
+    Ok(())
+}
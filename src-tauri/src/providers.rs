@@ -0,0 +1,123 @@
+//! Text generation for the few nodes the backend runs against a model
+//! itself, such as a `join` node summarizing its branches.
+//!
+//! Ollama is reached on its default local port. OpenAI needs an API key in
+//! `SQUADAID_OPENAI_API_KEY`, since provider settings otherwise live in the
+//! frontend. Both accept a seed, which makes their sampling repeatable.
+
+use crate::context::TokenUsage;
+use crate::metrics::AppMetrics;
+use serde_json::{json, Value};
+use tracing::field::Empty;
+use tracing::Instrument;
+
+const OLLAMA_URL: &str = "http://localhost:11434";
+const OPENAI_URL: &str = "https://api.openai.com/v1";
+const OPENAI_API_KEY_ENV: &str = "SQUADAID_OPENAI_API_KEY";
+
+/// @struct Completion
+/// The generated text and the tokens the provider reports for it.
+pub struct Completion {
+    pub text: String,
+    pub usage: TokenUsage,
+}
+
+/// # generate
+/// Sends a single prompt to `provider` and returns its completion. Each call
+/// is counted in `provider_calls` and traced as a `provider_call` span
+/// carrying the token counts.
+pub async fn generate(
+    metrics: &AppMetrics,
+    provider: &str,
+    model: &str,
+    prompt: &str,
+    seed: Option<u64>,
+) -> Result<Completion, String> {
+    let span = tracing::info_span!(
+        "provider_call",
+        provider,
+        model,
+        tokens.prompt = Empty,
+        tokens.completion = Empty
+    );
+    let completion = async {
+        match provider {
+            "ollama" => generate_ollama(model, prompt, seed).await,
+            "openai" => generate_openai(model, prompt, seed).await,
+            other => Err(format!(
+                "Provider '{}' cannot be called from the backend; use 'ollama' or 'openai'.",
+                other
+            )),
+        }
+    };
+    metrics.provider_call(provider);
+    let completion = completion.instrument(span.clone()).await?;
+    span.record("tokens.prompt", completion.usage.prompt_tokens);
+    span.record("tokens.completion", completion.usage.completion_tokens);
+    Ok(completion)
+}
+
+async fn generate_ollama(
+    model: &str,
+    prompt: &str,
+    seed: Option<u64>,
+) -> Result<Completion, String> {
+    let mut request = json!({ "model": model, "prompt": prompt, "stream": false });
+    if let Some(seed) = seed {
+        request["options"] = json!({ "seed": seed });
+    }
+    let res = reqwest::Client::new()
+        .post(format!("{}/api/generate", OLLAMA_URL))
+        .json(&request)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("Ollama API failed with status: {}", res.status()));
+    }
+    let body: Value = res.json().await.map_err(|e| e.to_string())?;
+    Ok(Completion {
+        text: body["response"].as_str().unwrap_or_default().to_string(),
+        usage: TokenUsage::from_counts(
+            body["prompt_eval_count"].as_u64().unwrap_or(0),
+            body["eval_count"].as_u64().unwrap_or(0),
+        ),
+    })
+}
+
+async fn generate_openai(
+    model: &str,
+    prompt: &str,
+    seed: Option<u64>,
+) -> Result<Completion, String> {
+    let key = std::env::var(OPENAI_API_KEY_ENV)
+        .map_err(|_| format!("Set {} to call OpenAI from a workflow.", OPENAI_API_KEY_ENV))?;
+    let mut request = json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": prompt }],
+    });
+    if let Some(seed) = seed {
+        request["seed"] = json!(seed);
+    }
+    let res = reqwest::Client::new()
+        .post(format!("{}/chat/completions", OPENAI_URL))
+        .bearer_auth(key)
+        .json(&request)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("OpenAI API failed with status: {}", res.status()));
+    }
+    let body: Value = res.json().await.map_err(|e| e.to_string())?;
+    Ok(Completion {
+        text: body["choices"][0]["message"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        usage: TokenUsage::from_counts(
+            body["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+            body["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+        ),
+    })
+}
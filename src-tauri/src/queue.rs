@@ -0,0 +1,456 @@
+//! Limits how many workflow runs execute at once, so a burst of runs does not
+//! overwhelm a shared provider such as a local Ollama instance. Runs beyond
+//! the limit wait in FIFO order. The limit is a setting persisted in the app
+//! data directory; until it is set, `SQUADAID_MAX_CONCURRENT_RUNS` or the
+//! default applies.
+//!
+//! A run only needs its slot while it has work to do: whenever all of its
+//! scheduled nodes are waiting on a person (an approval, a breakpoint, or a
+//! pause), the slot goes back to the queue until one of them continues.
+
+use crate::events::{LogLevel, RunEmitter, RunQueuedPayload};
+use crate::executor::RunControl;
+use crate::metrics::AppMetrics;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+const SETTINGS_FILE: &str = "run_queue_settings.json";
+const MAX_CONCURRENT_RUNS_ENV: &str = "SQUADAID_MAX_CONCURRENT_RUNS";
+const DEFAULT_MAX_CONCURRENT_RUNS: usize = 2;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct QueueSettings {
+    max_concurrent_runs: usize,
+}
+
+impl Default for QueueSettings {
+    fn default() -> Self {
+        let max_concurrent_runs = std::env::var(MAX_CONCURRENT_RUNS_ENV)
+            .ok()
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_RUNS);
+        QueueSettings {
+            max_concurrent_runs,
+        }
+    }
+}
+
+/// @struct RunQueue
+/// Managed state handing out run slots.
+pub struct RunQueue {
+    path: PathBuf,
+    settings: Mutex<QueueSettings>,
+    slots: Arc<Semaphore>,
+    waiting: AtomicUsize,
+    /// Slots still to be retired after the limit was lowered, once runs give
+    /// them back.
+    retiring: Arc<Mutex<usize>>,
+}
+
+impl RunQueue {
+    pub fn load(app: &tauri::App) -> Result<Self, String> {
+        let dir = app
+            .path_resolver()
+            .app_data_dir()
+            .ok_or_else(|| "Could not resolve app data directory".to_string())?;
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let path = dir.join(SETTINGS_FILE);
+        let settings: QueueSettings = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            Err(_) => QueueSettings::default(),
+        };
+        Ok(RunQueue::new(path, settings))
+    }
+
+    fn new(path: PathBuf, settings: QueueSettings) -> Self {
+        RunQueue {
+            path,
+            slots: Arc::new(Semaphore::new(settings.max_concurrent_runs)),
+            settings: Mutex::new(settings),
+            waiting: AtomicUsize::new(0),
+            retiring: Arc::default(),
+        }
+    }
+
+    fn save(&self, settings: &QueueSettings) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+        fs::write(&self.path, json).map_err(|e| e.to_string())
+    }
+
+    /// # set_limit
+    /// Changes how many runs may execute at once and writes it to disk.
+    fn set_limit(&self, max_concurrent_runs: usize) -> Result<(), String> {
+        if max_concurrent_runs == 0 {
+            return Err("At least one run must be allowed to execute.".to_string());
+        }
+        let mut settings = self.settings.lock().unwrap();
+        let previous = settings.max_concurrent_runs;
+        let updated = QueueSettings {
+            max_concurrent_runs,
+        };
+        self.save(&updated)?;
+        *settings = updated;
+
+        if max_concurrent_runs > previous {
+            self.grow(max_concurrent_runs - previous);
+        } else if max_concurrent_runs < previous {
+            self.shrink(previous - max_concurrent_runs);
+        }
+        Ok(())
+    }
+
+    /// Adds slots, first calling off retirements still waiting for a run to
+    /// give its slot back.
+    fn grow(&self, count: usize) {
+        let mut retiring = self.retiring.lock().unwrap();
+        let called_off = count.min(*retiring);
+        *retiring -= called_off;
+        self.slots.add_permits(count - called_off);
+    }
+
+    /// Retires free slots at once and the rest as runs give theirs back.
+    fn shrink(&self, count: usize) {
+        let mut retiring = self.retiring.lock().unwrap();
+        let mut pending = count;
+        while pending > 0 {
+            let Ok(permit) = self.slots.clone().try_acquire_owned() else {
+                break;
+            };
+            permit.forget();
+            pending -= 1;
+        }
+        if pending == 0 {
+            return;
+        }
+        *retiring += pending;
+        drop(retiring);
+
+        let slots = self.slots.clone();
+        let retiring = self.retiring.clone();
+        tauri::async_runtime::spawn(async move {
+            for _ in 0..pending {
+                let Ok(permit) = slots.clone().acquire_owned().await else {
+                    return;
+                };
+                let mut retiring = retiring.lock().unwrap();
+                // Raising the limit again called this retirement off; the
+                // permit goes back to the queue.
+                if *retiring == 0 {
+                    return;
+                }
+                *retiring -= 1;
+                permit.forget();
+            }
+        });
+    }
+
+    /// # acquire
+    /// Waits for a free slot, emitting `run-queued` if the run has to wait
+    /// and counting it in `queued_runs` meanwhile, then hands it to the run's
+    /// `RunSlot`. Returns `false` if the run was cancelled while it was
+    /// queued.
+    pub async fn acquire(
+        &self,
+        emitter: &RunEmitter,
+        control: &RunControl,
+        metrics: &AppMetrics,
+    ) -> Result<bool, String> {
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            control.slot.hold(self.slots.clone(), permit);
+            return Ok(true);
+        }
+
+        let position = self.waiting.fetch_add(1, Ordering::SeqCst) + 1;
+        metrics.run_queued();
+        let queued = async {
+            emitter.emit(RunQueuedPayload { position })?;
+            emitter.log(
+                LogLevel::Info,
+                format!(
+                    "[INFO] All run slots are busy; queued at position {}.",
+                    position
+                ),
+            )?;
+            Ok::<_, String>(tokio::select! {
+                permit = self.slots.clone().acquire_owned() => {
+                    Some(permit.expect("run queue semaphore is never closed"))
+                }
+                _ = control.cancel.cancelled() => None,
+            })
+        }
+        .await;
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        metrics.run_dequeued();
+        Ok(match queued? {
+            Some(permit) => {
+                control.slot.hold(self.slots.clone(), permit);
+                true
+            }
+            None => false,
+        })
+    }
+}
+
+#[derive(Default)]
+struct SlotState {
+    slots: Option<Arc<Semaphore>>,
+    permit: Option<OwnedSemaphorePermit>,
+    /// Scheduled nodes of the run that are not waiting on a person.
+    busy: usize,
+}
+
+impl SlotState {
+    fn idle(&mut self) {
+        self.busy = self.busy.saturating_sub(1);
+        if self.busy == 0 {
+            self.permit = None;
+        }
+    }
+}
+
+/// @struct RunSlot
+/// A run's hold on its queue slot, shared by all of its nodes. Each
+/// scheduled node counts as busy through a [`BusyGuard`] and stops counting
+/// while it waits in [`RunSlot::parked`]; the slot is given back whenever
+/// nothing is busy.
+#[derive(Clone, Default)]
+pub struct RunSlot {
+    state: Arc<Mutex<SlotState>>,
+}
+
+/// Counts a scheduled node as busy until dropped.
+pub struct BusyGuard(RunSlot);
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().idle();
+    }
+}
+
+/// Stops counting a node as busy until dropped, even if its wait is aborted.
+struct ParkedGuard<'a>(&'a RunSlot);
+
+impl Drop for ParkedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().busy += 1;
+    }
+}
+
+impl RunSlot {
+    fn hold(&self, slots: Arc<Semaphore>, permit: OwnedSemaphorePermit) {
+        let mut state = self.state.lock().unwrap();
+        state.slots = Some(slots);
+        state.permit = Some(permit);
+    }
+
+    /// Gives the slot back for good once the run has ended.
+    pub fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.slots = None;
+        state.permit = None;
+    }
+
+    /// Counts a node as busy from when it is scheduled until the guard drops.
+    pub fn busy(&self) -> BusyGuard {
+        self.state.lock().unwrap().busy += 1;
+        BusyGuard(self.clone())
+    }
+
+    /// # parked
+    /// Awaits `waiting` without counting the caller as busy, then claims the
+    /// slot again (unless the run is cancelled) before returning its output.
+    pub async fn parked<F: Future>(&self, cancel: &CancellationToken, waiting: F) -> F::Output {
+        let parked = {
+            self.state.lock().unwrap().idle();
+            ParkedGuard(self)
+        };
+        let output = waiting.await;
+        drop(parked);
+        self.ready(cancel).await;
+        output
+    }
+
+    /// Waits until the run holds its slot again, if it gave it back.
+    /// Returns early if the run is cancelled.
+    pub async fn ready(&self, cancel: &CancellationToken) {
+        let slots = {
+            let state = self.state.lock().unwrap();
+            match (&state.permit, &state.slots) {
+                (None, Some(slots)) => slots.clone(),
+                _ => return,
+            }
+        };
+        let permit = tokio::select! {
+            permit = slots.acquire_owned() => permit.expect("run queue semaphore is never closed"),
+            _ = cancel.cancelled() => return,
+        };
+        let mut state = self.state.lock().unwrap();
+        // Another node of the run may have claimed it first.
+        if state.permit.is_none() && state.slots.is_some() {
+            state.permit = Some(permit);
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn get_max_concurrent_runs(queue: tauri::State<'_, RunQueue>) -> Result<usize, String> {
+    Ok(queue.settings.lock().unwrap().max_concurrent_runs)
+}
+
+/// # set_max_concurrent_runs
+/// Changes how many runs may execute at once and writes it to disk. Lowering
+/// the limit lets runs already executing finish; queued runs start once the
+/// number executing is below the new limit.
+#[tauri::command]
+pub async fn set_max_concurrent_runs(
+    queue: tauri::State<'_, RunQueue>,
+    max_concurrent_runs: usize,
+) -> Result<(), String> {
+    queue.set_limit(max_concurrent_runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn queue(name: &str, max_concurrent_runs: usize) -> RunQueue {
+        let path = std::env::temp_dir().join(format!("squadaid-{}-{}", name, SETTINGS_FILE));
+        RunQueue::new(
+            path,
+            QueueSettings {
+                max_concurrent_runs,
+            },
+        )
+    }
+
+    async fn settles_at(slots: &Semaphore, expected: usize) -> bool {
+        for _ in 0..100 {
+            if slots.available_permits() == expected {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        false
+    }
+
+    fn held(slots: &Arc<Semaphore>) -> RunSlot {
+        let slot = RunSlot::default();
+        slot.hold(slots.clone(), slots.clone().try_acquire_owned().unwrap());
+        slot
+    }
+
+    #[test]
+    fn lowering_the_limit_retires_free_slots_at_once() {
+        let queue = queue("lower", 3);
+        queue.set_limit(1).unwrap();
+        assert_eq!(queue.slots.available_permits(), 1);
+        queue.set_limit(2).unwrap();
+        assert_eq!(queue.slots.available_permits(), 2);
+    }
+
+    #[test]
+    fn raising_the_limit_calls_off_pending_retirements() {
+        tauri::async_runtime::block_on(async {
+            let queue = queue("lower-raise", 2);
+            let running = queue.slots.clone().acquire_many_owned(2).await.unwrap();
+
+            queue.set_limit(1).unwrap();
+            queue.set_limit(2).unwrap();
+            assert_eq!(*queue.retiring.lock().unwrap(), 0);
+
+            drop(running);
+            assert!(settles_at(&queue.slots, 2).await);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(queue.slots.available_permits(), 2);
+        });
+    }
+
+    #[test]
+    fn busy_slot_is_given_back_when_every_node_is_done() {
+        let slots = Arc::new(Semaphore::new(1));
+        let slot = held(&slots);
+        let first = slot.busy();
+        let second = slot.busy();
+
+        drop(first);
+        assert_eq!(slots.available_permits(), 0);
+        drop(second);
+        assert_eq!(slots.available_permits(), 1);
+    }
+
+    #[test]
+    fn parked_node_gives_the_slot_back_until_it_continues() {
+        tauri::async_runtime::block_on(async {
+            let slots = Arc::new(Semaphore::new(1));
+            let slot = held(&slots);
+            let cancel = CancellationToken::new();
+            let _busy = slot.busy();
+
+            let available = slot
+                .parked(&cancel, async { slots.available_permits() })
+                .await;
+
+            assert_eq!(available, 1);
+            assert_eq!(slots.available_permits(), 0);
+        });
+    }
+
+    #[test]
+    fn parked_node_keeps_the_slot_while_another_is_busy() {
+        tauri::async_runtime::block_on(async {
+            let slots = Arc::new(Semaphore::new(1));
+            let slot = held(&slots);
+            let cancel = CancellationToken::new();
+            let _waiting = slot.busy();
+            let _working = slot.busy();
+
+            let available = slot
+                .parked(&cancel, async { slots.available_permits() })
+                .await;
+
+            assert_eq!(available, 0);
+        });
+    }
+
+    #[test]
+    fn cancelled_run_does_not_wait_to_claim_the_slot_again() {
+        tauri::async_runtime::block_on(async {
+            let slots = Arc::new(Semaphore::new(1));
+            let slot = held(&slots);
+            let cancel = CancellationToken::new();
+            let _busy = slot.busy();
+
+            let other = slot
+                .parked(&cancel, async {
+                    let other = slots.clone().try_acquire_owned().unwrap();
+                    cancel.cancel();
+                    other
+                })
+                .await;
+
+            assert_eq!(slots.available_permits(), 0);
+            drop(other);
+            assert_eq!(slots.available_permits(), 1);
+        });
+    }
+
+    #[test]
+    fn released_slot_stays_released() {
+        let slots = Arc::new(Semaphore::new(1));
+        let slot = held(&slots);
+        let busy = slot.busy();
+
+        slot.release();
+        assert_eq!(slots.available_permits(), 1);
+        drop(busy);
+        assert_eq!(slots.available_permits(), 1);
+    }
+}
@@ -0,0 +1,179 @@
+//! Persisted execution logs: every `execution-log` line of a run is also
+//! written to the `run_logs` table, so it outlives the window that showed it.
+//! Every event a run emits is likewise appended to `run_events`, which is
+//! what `replay_workflow_run` plays back.
+//!
+//! Lines and events are handed to a background writer over a channel,
+//! keeping database latency out of the executor; the single writer keeps
+//! them in order.
+
+use crate::db::Db;
+use crate::events::LogLevel;
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+use tracing::Level;
+
+/// @struct RunLogEntry
+/// One stored log line. `id` increases in emission order and is the cursor
+/// for `get_run_logs`.
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct RunLogEntry {
+    pub id: i64,
+    pub run_id: String,
+    pub node_id: Option<String>,
+    pub iteration: Option<u32>,
+    pub level: String,
+    pub message: String,
+    pub timestamp: String,
+}
+
+enum Pending {
+    Line(PendingLine),
+    Event(PendingEvent),
+}
+
+struct PendingEvent {
+    run_id: String,
+    name: String,
+    envelope: String,
+}
+
+struct PendingLine {
+    run_id: String,
+    node_id: Option<String>,
+    iteration: Option<u32>,
+    level: LogLevel,
+    message: String,
+    timestamp: String,
+}
+
+/// @struct RunLogWriter
+/// Managed state feeding log lines and events to the background writer.
+pub struct RunLogWriter {
+    sender: mpsc::UnboundedSender<Pending>,
+}
+
+impl RunLogWriter {
+    pub fn spawn(pool: SqlitePool) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Pending>();
+        tauri::async_runtime::spawn(async move {
+            while let Some(pending) = receiver.recv().await {
+                let stored = match pending {
+                    Pending::Line(line) => insert(&pool, line).await,
+                    Pending::Event(event) => insert_event(&pool, event).await,
+                };
+                if let Err(e) = stored {
+                    tracing::warn!("Could not store workflow log line or event: {}", e);
+                }
+            }
+        });
+        RunLogWriter { sender }
+    }
+
+    /// Appends an emitted event, exactly as sent, to the run's history.
+    pub fn record_event(&self, run_id: &str, name: &str, envelope: &serde_json::Value) {
+        // Only fails once the writer task is gone, i.e. during shutdown.
+        let _ = self.sender.send(Pending::Event(PendingEvent {
+            run_id: run_id.to_string(),
+            name: name.to_string(),
+            envelope: envelope.to_string(),
+        }));
+    }
+
+    pub fn record(
+        &self,
+        run_id: &str,
+        node_id: Option<&str>,
+        iteration: Option<u32>,
+        level: LogLevel,
+        message: &str,
+    ) {
+        // Only fails once the writer task is gone, i.e. during shutdown.
+        let _ = self.sender.send(Pending::Line(PendingLine {
+            run_id: run_id.to_string(),
+            node_id: node_id.map(str::to_string),
+            iteration,
+            level,
+            message: message.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+        }));
+    }
+}
+
+async fn insert(pool: &SqlitePool, line: PendingLine) -> Result<(), String> {
+    sqlx::query(
+        "INSERT INTO run_logs (run_id, node_id, iteration, level, message, timestamp) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&line.run_id)
+    .bind(&line.node_id)
+    .bind(line.iteration)
+    .bind(line.level.as_str())
+    .bind(&line.message)
+    .bind(&line.timestamp)
+    .execute(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn insert_event(pool: &SqlitePool, event: PendingEvent) -> Result<(), String> {
+    sqlx::query("INSERT INTO run_events (run_id, name, envelope) VALUES (?, ?, ?)")
+        .bind(&event.run_id)
+        .bind(&event.name)
+        .bind(&event.envelope)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// # get_run_logs
+/// Returns a run's log lines at or above `level` (default `INFO`), oldest
+/// first. Pass the `id` of the last line received as `after` to fetch the
+/// next page.
+#[tauri::command]
+pub async fn get_run_logs(
+    db: tauri::State<'_, Db>,
+    run_id: String,
+    level: Option<String>,
+    after: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Vec<RunLogEntry>, String> {
+    let min_level: Level = level
+        .as_deref()
+        .unwrap_or("INFO")
+        .parse()
+        .map_err(|_| format!("Unknown log level: {}", level.unwrap_or_default()))?;
+    // `Level` orders by verbosity, so "at least as severe" is `<=`.
+    let levels: Vec<&str> = [
+        Level::ERROR,
+        Level::WARN,
+        Level::INFO,
+        Level::DEBUG,
+        Level::TRACE,
+    ]
+    .into_iter()
+    .filter(|l| *l <= min_level)
+    .map(|l| l.as_str())
+    .collect();
+    let levels = serde_json::to_string(&levels).map_err(|e| e.to_string())?;
+
+    sqlx::query_as::<_, RunLogEntry>(
+        "SELECT id, run_id, node_id, iteration, level, message, timestamp
+         FROM run_logs
+         WHERE run_id = ?1
+           AND id > ?2
+           AND level IN (SELECT value FROM json_each(?3))
+         ORDER BY id
+         LIMIT ?4",
+    )
+    .bind(&run_id)
+    .bind(after.unwrap_or(0))
+    .bind(levels)
+    .bind(limit.unwrap_or(500))
+    .fetch_all(&db.pool)
+    .await
+    .map_err(|e| e.to_string())
+}
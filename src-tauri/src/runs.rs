@@ -0,0 +1,445 @@
+//! Run history: one `workflow_runs` row per `run_workflow` invocation.
+
+use crate::context::{NodeResult, TokenUsage};
+use crate::db::Db;
+use crate::events::RecordedEvent;
+use crate::executor::{Checkpoint, ExecutionOptions};
+use crate::metrics::AppMetrics;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Replays never wait longer than this between two events, however slow the
+/// original run was.
+const MAX_REPLAY_GAP: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+    /// Was queued or running when the app last exited; see `resume_run`.
+    Interrupted,
+}
+
+impl RunStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            RunStatus::Queued => "queued",
+            RunStatus::Running => "running",
+            RunStatus::Completed => "completed",
+            RunStatus::Failed => "failed",
+            RunStatus::Cancelled => "cancelled",
+            RunStatus::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// @struct WorkflowRunSummary
+/// A row of the run history list.
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct WorkflowRunSummary {
+    pub run_id: String,
+    pub workflow_id: Option<String>,
+    pub status: String,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub error: Option<String>,
+    /// Set for runs started with a seed; run again with it to reproduce.
+    pub seed: Option<i64>,
+}
+
+/// @struct WorkflowRun
+/// A single run including what each node produced.
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkflowRun {
+    #[serde(flatten)]
+    pub summary: WorkflowRunSummary,
+    pub node_results: HashMap<String, NodeResult>,
+}
+
+#[derive(sqlx::FromRow)]
+struct RunRow {
+    #[sqlx(flatten)]
+    summary: WorkflowRunSummary,
+    node_results: String,
+}
+
+/// Records a new run as queued, keeping its graph, options and seed so it can
+/// be resumed. A resumed run keeps its original row.
+pub async fn insert_run(
+    db: &Db,
+    run_id: &str,
+    workflow_id: Option<&str>,
+    graph_state_json: &str,
+    options: &ExecutionOptions,
+) -> Result<(), String> {
+    let options_json = serde_json::to_string(options).map_err(|e| e.to_string())?;
+    sqlx::query(
+        "INSERT OR IGNORE INTO workflow_runs (run_id, workflow_id, status, started_at, graph_state, seed, options) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(run_id)
+    .bind(workflow_id)
+    .bind(RunStatus::Queued.as_str())
+    .bind(Utc::now().to_rfc3339())
+    .bind(graph_state_json)
+    .bind(options.seed.map(|seed| seed as i64))
+    .bind(options_json)
+    .execute(&db.pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Records that a queued run got a slot and started executing.
+pub async fn mark_running(db: &Db, run_id: &str) -> Result<(), String> {
+    sqlx::query("UPDATE workflow_runs SET status = ? WHERE run_id = ?")
+        .bind(RunStatus::Running.as_str())
+        .bind(run_id)
+        .execute(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub async fn save_checkpoint(db: &Db, run_id: &str, checkpoint: &Checkpoint) -> Result<(), String> {
+    let checkpoint = serde_json::to_string(checkpoint).map_err(|e| e.to_string())?;
+    sqlx::query("UPDATE workflow_runs SET checkpoint = ? WHERE run_id = ?")
+        .bind(checkpoint)
+        .bind(run_id)
+        .execute(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Marks every run still recorded as queued or running as interrupted. Called
+/// once at startup, before any run can start, so none of them is really in
+/// progress.
+pub async fn mark_interrupted_runs(db: &Db) -> Result<(), String> {
+    let marked = sqlx::query("UPDATE workflow_runs SET status = ?1 WHERE status IN (?2, ?3)")
+        .bind(RunStatus::Interrupted.as_str())
+        .bind(RunStatus::Queued.as_str())
+        .bind(RunStatus::Running.as_str())
+        .execute(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .rows_affected();
+    if marked > 0 {
+        tracing::info!(
+            "Marked {} unfinished workflow run(s) as interrupted",
+            marked
+        );
+    }
+    Ok(())
+}
+
+/// @struct InterruptedRun
+/// What `resume_run` needs to pick up a run that never finished. `options`
+/// are the ones the run started with, set to resume from its checkpoint.
+pub struct InterruptedRun {
+    pub workflow_id: Option<String>,
+    pub graph_state_json: String,
+    pub options: ExecutionOptions,
+}
+
+/// Loads a run that is marked as interrupted, or still as queued or running.
+/// Callers must make sure it is not actually in progress in this process.
+pub async fn load_interrupted_run(db: &Db, run_id: &str) -> Result<InterruptedRun, String> {
+    let (workflow_id, status, graph_state, checkpoint, seed, options): (
+        Option<String>,
+        String,
+        Option<String>,
+        Option<String>,
+        Option<i64>,
+        Option<String>,
+    ) = sqlx::query_as(
+        "SELECT workflow_id, status, graph_state, checkpoint, seed, options FROM workflow_runs WHERE run_id = ?",
+    )
+    .bind(run_id)
+    .fetch_optional(&db.pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("No workflow run with id '{}'.", run_id))?;
+
+    let unfinished = [
+        RunStatus::Interrupted,
+        RunStatus::Running,
+        RunStatus::Queued,
+    ];
+    if !unfinished
+        .iter()
+        .any(|unfinished| unfinished.as_str() == status)
+    {
+        return Err(format!(
+            "Workflow run '{}' already ended ({}) and cannot be resumed.",
+            run_id, status
+        ));
+    }
+    let graph_state_json = graph_state
+        .ok_or_else(|| format!("Workflow run '{}' was not recorded with its graph.", run_id))?;
+    let checkpoint = checkpoint
+        .map(|json| serde_json::from_str(&json))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    // Runs recorded before options were stored resume with the defaults.
+    let options = options
+        .map(|json| serde_json::from_str::<ExecutionOptions>(&json))
+        .transpose()
+        .map_err(|e| e.to_string())?
+        .unwrap_or_default();
+    Ok(InterruptedRun {
+        workflow_id,
+        graph_state_json,
+        options: ExecutionOptions {
+            resume_from: checkpoint,
+            seed: seed.map(|seed| seed as u64),
+            ..options
+        },
+    })
+}
+
+/// Records how a run ended, what each of its nodes produced, and its duration
+/// and token totals. Its events are stored as they are emitted.
+pub async fn finish_run(
+    db: &Db,
+    run_id: &str,
+    status: RunStatus,
+    error: Option<&str>,
+    node_results: &HashMap<String, NodeResult>,
+    usage: &TokenUsage,
+) -> Result<(), String> {
+    let node_results = serde_json::to_string(node_results).map_err(|e| e.to_string())?;
+    sqlx::query(
+        "UPDATE workflow_runs
+         SET status = ?1, finished_at = ?2, error = ?3, node_results = ?4,
+             duration_ms = CAST((julianday(?2) - julianday(started_at)) * 86400000 AS INTEGER),
+             prompt_tokens = ?5, completion_tokens = ?6, total_tokens = ?7, cost_usd = ?8
+         WHERE run_id = ?9",
+    )
+    .bind(status.as_str())
+    .bind(Utc::now().to_rfc3339())
+    .bind(error)
+    .bind(node_results)
+    .bind(usage.prompt_tokens as i64)
+    .bind(usage.completion_tokens as i64)
+    .bind(usage.total_tokens as i64)
+    .bind(usage.cost_usd)
+    .bind(run_id)
+    .execute(&db.pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// # get_workflow_runs
+/// Lists past runs, newest first, optionally only those of one workflow.
+#[tauri::command]
+pub async fn get_workflow_runs(
+    db: tauri::State<'_, Db>,
+    metrics: tauri::State<'_, AppMetrics>,
+    workflow_id: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<WorkflowRunSummary>, String> {
+    metrics
+        .db_query(recent_runs(
+            &db,
+            workflow_id.as_deref(),
+            limit.unwrap_or(100),
+        ))
+        .await
+}
+
+/// The latest `limit` runs, newest first, optionally only of one workflow.
+pub async fn recent_runs(
+    db: &Db,
+    workflow_id: Option<&str>,
+    limit: u32,
+) -> Result<Vec<WorkflowRunSummary>, String> {
+    sqlx::query_as::<_, WorkflowRunSummary>(
+        "SELECT run_id, workflow_id, status, started_at, finished_at, error, seed
+         FROM workflow_runs
+         WHERE ?1 IS NULL OR workflow_id = ?1
+         ORDER BY started_at DESC
+         LIMIT ?2",
+    )
+    .bind(workflow_id)
+    .bind(limit)
+    .fetch_all(&db.pool)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// # get_run_details
+/// Returns one run with its per-node results.
+#[tauri::command]
+pub async fn get_run_details(
+    db: tauri::State<'_, Db>,
+    metrics: tauri::State<'_, AppMetrics>,
+    run_id: String,
+) -> Result<WorkflowRun, String> {
+    let query = sqlx::query_as::<_, RunRow>(
+        "SELECT run_id, workflow_id, status, started_at, finished_at, error, seed, node_results
+         FROM workflow_runs WHERE run_id = ?",
+    )
+    .bind(&run_id);
+    let row = metrics
+        .db_query(query.fetch_optional(&db.pool))
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No workflow run with id '{}'.", run_id))?;
+
+    Ok(WorkflowRun {
+        summary: row.summary,
+        node_results: serde_json::from_str(&row.node_results).map_err(|e| e.to_string())?,
+    })
+}
+
+/// @struct NodeMetrics
+/// Duration and usage of one node. For nodes inside a loop this is the last
+/// iteration; the run totals include every iteration.
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeMetrics {
+    pub node_id: String,
+    pub node_type: String,
+    pub started_at: String,
+    pub duration_ms: u64,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+}
+
+/// @struct RunMetrics
+/// Totals for a run plus a per-node breakdown, ordered by start time.
+#[derive(Serialize, Debug, Clone)]
+pub struct RunMetrics {
+    pub run_id: String,
+    pub status: String,
+    /// Wall-clock time from queueing to the end; `None` while still running.
+    pub duration_ms: Option<i64>,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+    pub nodes: Vec<NodeMetrics>,
+}
+
+#[derive(sqlx::FromRow)]
+struct MetricsRow {
+    status: String,
+    duration_ms: Option<i64>,
+    prompt_tokens: i64,
+    completion_tokens: i64,
+    total_tokens: i64,
+    cost_usd: f64,
+    node_results: String,
+}
+
+/// # get_run_metrics
+/// Returns a run's duration, token usage and cost, overall and per node.
+#[tauri::command]
+pub async fn get_run_metrics(
+    db: tauri::State<'_, Db>,
+    metrics: tauri::State<'_, AppMetrics>,
+    run_id: String,
+) -> Result<RunMetrics, String> {
+    let query = sqlx::query_as::<_, MetricsRow>(
+        "SELECT status, duration_ms, prompt_tokens, completion_tokens, total_tokens, cost_usd, node_results
+         FROM workflow_runs WHERE run_id = ?",
+    )
+    .bind(&run_id);
+    let row = metrics
+        .db_query(query.fetch_optional(&db.pool))
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No workflow run with id '{}'.", run_id))?;
+
+    let node_results: HashMap<String, NodeResult> =
+        serde_json::from_str(&row.node_results).map_err(|e| e.to_string())?;
+    let mut nodes: Vec<NodeMetrics> = node_results
+        .into_iter()
+        .map(|(node_id, result)| NodeMetrics {
+            node_id,
+            node_type: result.node_type,
+            started_at: result.started_at,
+            duration_ms: result.duration_ms,
+            usage: result.usage.unwrap_or_default(),
+        })
+        .collect();
+    nodes.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+
+    Ok(RunMetrics {
+        run_id,
+        status: row.status,
+        duration_ms: row.duration_ms,
+        usage: TokenUsage {
+            prompt_tokens: row.prompt_tokens as u64,
+            completion_tokens: row.completion_tokens as u64,
+            total_tokens: row.total_tokens as u64,
+            cost_usd: row.cost_usd,
+        },
+        nodes,
+    })
+}
+
+/// The events stored for a run, oldest first.
+async fn recorded_events(db: &Db, run_id: &str) -> Result<Vec<RecordedEvent>, String> {
+    sqlx::query_scalar::<_, i64>("SELECT 1 FROM workflow_runs WHERE run_id = ?")
+        .bind(run_id)
+        .fetch_optional(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No workflow run with id '{}'.", run_id))?;
+    let rows: Vec<(String, String)> =
+        sqlx::query_as("SELECT name, envelope FROM run_events WHERE run_id = ? ORDER BY id")
+            .bind(run_id)
+            .fetch_all(&db.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    rows.into_iter()
+        .map(|(name, envelope)| {
+            Ok(RecordedEvent {
+                name,
+                envelope: serde_json::from_str(&envelope).map_err(|e| e.to_string())?,
+            })
+        })
+        .collect()
+}
+
+/// # replay_workflow_run
+/// Re-emits the events recorded for a run, in their original order
+/// and under their original names, with `"replay": true` added so live
+/// listeners can tell them apart. Without `speed` the events are sent back to
+/// back; with it the original gaps are kept, divided by `speed` (so `2.0`
+/// plays twice as fast).
+#[tauri::command]
+pub async fn replay_workflow_run(
+    window: tauri::Window,
+    db: tauri::State<'_, Db>,
+    run_id: String,
+    speed: Option<f64>,
+) -> Result<(), String> {
+    let events = recorded_events(&db, &run_id).await?;
+    if let Some(speed) = speed {
+        if !speed.is_finite() || speed <= 0.0 {
+            return Err("Replay speed must be greater than zero.".to_string());
+        }
+    }
+
+    let mut previous: Option<DateTime<Utc>> = None;
+    for RecordedEvent { name, mut envelope } in events {
+        let timestamp = envelope["timestamp"]
+            .as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Utc));
+        if let (Some(speed), Some(previous), Some(timestamp)) = (speed, previous, timestamp) {
+            let gap = (timestamp - previous).to_std().unwrap_or_default();
+            tokio::time::sleep(gap.div_f64(speed).min(MAX_REPLAY_GAP)).await;
+        }
+        previous = timestamp.or(previous);
+
+        envelope["replay"] = serde_json::Value::Bool(true);
+        window.emit(&name, envelope).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
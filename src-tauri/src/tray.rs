@@ -0,0 +1,129 @@
+use crate::db::Db;
+use crate::executor::RunRegistry;
+use crate::metrics::AppMetrics;
+use crate::runs::{self, WorkflowRunSummary};
+use tauri::{
+    AppHandle, CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu,
+    SystemTrayMenuItem, SystemTraySubmenu,
+};
+
+const ACTIVE_RUNS_ITEM: &str = "active_runs";
+const PENDING_APPROVALS_ITEM: &str = "pending_approvals";
+const PAUSE_ALL_ITEM: &str = "pause_all";
+const RESUME_ALL_ITEM: &str = "resume_all";
+/// Followed by the run id.
+const RECENT_RUN_ITEM_PREFIX: &str = "recent_run:";
+const OPEN_ITEM: &str = "open";
+const QUIT_ITEM: &str = "quit";
+
+/// How many runs the "Recent runs" submenu lists.
+const RECENT_RUNS: u32 = 5;
+
+fn plural(count: usize, one: &str, many: &str) -> String {
+    match count {
+        0 => format!("No {}", many),
+        1 => format!("1 {}", one),
+        n => format!("{} {}", n, many),
+    }
+}
+
+fn menu(
+    active_runs: &str,
+    pending_approvals: usize,
+    recent: &[WorkflowRunSummary],
+) -> SystemTrayMenu {
+    let approvals = CustomMenuItem::new(
+        PENDING_APPROVALS_ITEM,
+        plural(pending_approvals, "pending approval", "pending approvals"),
+    );
+    let approvals = if pending_approvals == 0 {
+        approvals.disabled()
+    } else {
+        approvals
+    };
+    let recent_menu = if recent.is_empty() {
+        SystemTrayMenu::new()
+            .add_item(CustomMenuItem::new("no_recent_runs", "No runs yet").disabled())
+    } else {
+        recent.iter().fold(SystemTrayMenu::new(), |menu, run| {
+            let started = run
+                .started_at
+                .get(..16)
+                .unwrap_or(&run.started_at)
+                .replace('T', " ");
+            menu.add_item(CustomMenuItem::new(
+                format!("{}{}", RECENT_RUN_ITEM_PREFIX, run.run_id),
+                format!("{} - {}", started, run.status),
+            ))
+        })
+    };
+    SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new(ACTIVE_RUNS_ITEM, active_runs).disabled())
+        .add_item(approvals)
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(PAUSE_ALL_ITEM, "Pause all runs"))
+        .add_item(CustomMenuItem::new(RESUME_ALL_ITEM, "Resume all runs"))
+        .add_submenu(SystemTraySubmenu::new("Recent runs", recent_menu))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(OPEN_ITEM, "Open SquadAID"))
+        .add_item(CustomMenuItem::new(QUIT_ITEM, "Quit"))
+}
+
+/// # build
+/// The tray icon itself comes from `tauri.systemTray` in the app config.
+pub fn build() -> SystemTray {
+    SystemTray::new()
+        .with_menu(menu("No active runs", 0, &[]))
+        .with_tooltip("SquadAID")
+}
+
+fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+/// Recent runs open the main window, where run history shows them; the UI
+/// has no per-run view to jump to yet.
+pub fn handle_event(app: &AppHandle, event: SystemTrayEvent) {
+    match event {
+        SystemTrayEvent::LeftClick { .. } => show_main_window(app),
+        SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
+            OPEN_ITEM | PENDING_APPROVALS_ITEM => show_main_window(app),
+            PAUSE_ALL_ITEM => {
+                app.state::<RunRegistry>().pause_all();
+            }
+            RESUME_ALL_ITEM => {
+                app.state::<RunRegistry>().resume_all();
+            }
+            QUIT_ITEM => app.exit(0),
+            id if id.starts_with(RECENT_RUN_ITEM_PREFIX) => show_main_window(app),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+/// # refresh
+/// Rebuilds the tray menu and tooltip from the active runs, pending
+/// approvals and run history. Call whenever a run starts or finishes, and
+/// whenever an approval is requested or decided.
+pub fn refresh(app: &AppHandle) {
+    let active = app.state::<AppMetrics>().snapshot().active_runs as usize;
+    let label = plural(active, "active run", "active runs");
+    let pending_approvals = app.state::<RunRegistry>().pending_approvals();
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let recent = runs::recent_runs(&app.state::<Db>(), None, RECENT_RUNS)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Could not list recent runs for the tray: {}", e);
+                Vec::new()
+            });
+        let tray = app.tray_handle();
+        let _ = tray.set_menu(menu(&label, pending_approvals, &recent));
+        let _ = tray.set_tooltip(&format!("SquadAID - {}", label));
+    });
+}
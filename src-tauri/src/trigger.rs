@@ -0,0 +1,383 @@
+//! A small local HTTP endpoint that lets external systems (CI, GitHub
+//! webhooks) start a saved workflow by name:
+//!
+//! ```text
+//! POST /workflows/<name>/run
+//! Authorization: Bearer <token>      (or ?token=<token>)
+//!
+//! {"topic": "release notes"}         (optional workflow parameters)
+//! ```
+//!
+//! It only starts when both `SQUADAID_TRIGGER_PORT` and
+//! `SQUADAID_TRIGGER_TOKEN` are set, and binds to 127.0.0.1; put a tunnel or
+//! reverse proxy in front of it to accept webhooks from outside the machine.
+//! A triggered run behaves exactly like one started from the UI, and the
+//! response carries its run id.
+
+use crate::db::Db;
+use crate::events::RunEmitter;
+use crate::executor::{ExecutionOptions, RunRegistry};
+use std::time::Duration;
+use tauri::Manager;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const TRIGGER_PORT_ENV: &str = "SQUADAID_TRIGGER_PORT";
+const TRIGGER_TOKEN_ENV: &str = "SQUADAID_TRIGGER_TOKEN";
+
+/// Requests whose headers or body do not fit are rejected.
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause after a failed accept (e.g. out of file descriptors) before trying
+/// again, so the loop does not spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+struct Response {
+    status: &'static str,
+    body: serde_json::Value,
+}
+
+impl Response {
+    fn error(status: &'static str, message: &str) -> Self {
+        Response {
+            status,
+            body: serde_json::json!({ "error": message }),
+        }
+    }
+}
+
+/// # spawn_trigger_listener
+/// Starts the trigger endpoint in the background if it is configured.
+pub fn spawn_trigger_listener(app: tauri::AppHandle) {
+    let Some(port) = std::env::var(TRIGGER_PORT_ENV)
+        .ok()
+        .and_then(|p| p.parse::<u16>().ok())
+    else {
+        return;
+    };
+    let Some(token) = std::env::var(TRIGGER_TOKEN_ENV)
+        .ok()
+        .filter(|token| !token.is_empty())
+    else {
+        tracing::warn!(
+            "{} is set but {} is not; the workflow trigger endpoint stays disabled.",
+            TRIGGER_PORT_ENV,
+            TRIGGER_TOKEN_ENV
+        );
+        return;
+    };
+
+    tauri::async_runtime::spawn(async move {
+        let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!(
+                    "Could not bind workflow trigger listener on port {}: {}",
+                    port,
+                    e
+                );
+                return;
+            }
+        };
+        tracing::info!("Accepting workflow triggers on 127.0.0.1:{}", port);
+
+        loop {
+            let socket = match listener.accept().await {
+                Ok((socket, _)) => socket,
+                Err(e) => {
+                    tracing::warn!("Could not accept workflow trigger connection: {}", e);
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            let app = app.clone();
+            let token = token.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = serve(socket, &app, &token).await {
+                    tracing::warn!("Workflow trigger request failed: {}", e);
+                }
+            });
+        }
+    });
+}
+
+async fn serve(mut socket: TcpStream, app: &tauri::AppHandle, token: &str) -> Result<(), String> {
+    let response = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut socket)).await {
+        Ok(Ok((head, body))) => handle(app, token, &head, &body).await,
+        Ok(Err(response)) => response,
+        Err(_) => Response::error(
+            "408 Request Timeout",
+            "The request took too long to arrive.",
+        ),
+    };
+    let body = response.body.to_string();
+    let raw = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        body.len(),
+        body
+    );
+    socket
+        .write_all(raw.as_bytes())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Reads the request line and headers, then as much body as
+/// `Content-Length` announces.
+async fn read_request<R: AsyncRead + Unpin>(socket: &mut R) -> Result<(String, Vec<u8>), Response> {
+    let malformed = || Response::error("400 Bad Request", "Malformed request.");
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let head_end = loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        if buf.len() > MAX_HEADER_BYTES {
+            return Err(malformed());
+        }
+        let read = socket.read(&mut chunk).await.map_err(|_| malformed())?;
+        if read == 0 {
+            return Err(malformed());
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+    let mut body = buf.split_off(head_end + 4);
+    let head = String::from_utf8(buf).map_err(|_| malformed())?;
+
+    let length = match header(&head, "content-length") {
+        Some(value) => value.parse::<usize>().map_err(|_| malformed())?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(Response::error(
+            "413 Payload Too Large",
+            "Request body is too large.",
+        ));
+    }
+    while body.len() < length {
+        let read = socket.read(&mut chunk).await.map_err(|_| malformed())?;
+        if read == 0 {
+            return Err(malformed());
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    body.truncate(length);
+    Ok((head, body))
+}
+
+/// The value of the first header called `name`, ignoring case.
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(header, _)| header.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+async fn handle(app: &tauri::AppHandle, token: &str, head: &str, body: &[u8]) -> Response {
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Response::error("400 Bad Request", "Malformed request line.");
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let bearer = header(head, "authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    let query_token = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="));
+    if !bearer
+        .or(query_token)
+        .map_or(false, |given| tokens_match(given, token))
+    {
+        return Response::error("401 Unauthorized", "Missing or invalid token.");
+    }
+
+    let Some(name) = path
+        .strip_prefix("/workflows/")
+        .and_then(|rest| rest.strip_suffix("/run"))
+        .filter(|name| !name.is_empty() && !name.contains('/'))
+    else {
+        return Response::error("404 Not Found", "Expected POST /workflows/<name>/run.");
+    };
+    if method != "POST" {
+        return Response::error("405 Method Not Allowed", "Use POST to trigger a workflow.");
+    }
+    let name = percent_decode(name);
+    // The body, if any, carries the workflow's parameters.
+    let parameters = if body.iter().all(u8::is_ascii_whitespace) {
+        serde_json::Map::new()
+    } else {
+        match serde_json::from_slice(body) {
+            Ok(serde_json::Value::Object(parameters)) => parameters,
+            _ => {
+                return Response::error(
+                    "400 Bad Request",
+                    "The request body must be a JSON object of workflow parameters.",
+                )
+            }
+        }
+    };
+
+    let workflow: Result<Option<(String, String)>, _> =
+        sqlx::query_as("SELECT id, graph_state FROM workflows WHERE name = ?")
+            .bind(&name)
+            .fetch_optional(&app.state::<Db>().pool)
+            .await;
+    let (workflow_id, graph_state_json) = match workflow {
+        Ok(Some(workflow)) => workflow,
+        Ok(None) => {
+            return Response::error("404 Not Found", &format!("No workflow named '{}'.", name))
+        }
+        Err(e) => {
+            tracing::error!("Could not look up workflow '{}': {}", name, e);
+            return Response::error(
+                "500 Internal Server Error",
+                "Could not look up the workflow.",
+            );
+        }
+    };
+    let Some(window) = app.get_window("main") else {
+        return Response::error("503 Service Unavailable", "The app window is not open.");
+    };
+
+    let emitter = RunEmitter::new(window.clone());
+    let run_id = emitter.run_id().to_string();
+    let control = match app.state::<RunRegistry>().try_register(&run_id) {
+        Ok(control) => control,
+        Err(e) => return Response::error("500 Internal Server Error", &e),
+    };
+    tracing::info!(run_id = %run_id, "Workflow '{}' triggered over HTTP", name);
+    tauri::async_runtime::spawn(async move {
+        // Failures are reported through the usual run events and notifications.
+        let _ = crate::drive_run(
+            window,
+            emitter,
+            control,
+            graph_state_json,
+            Some(workflow_id),
+            ExecutionOptions {
+                parameters,
+                ..ExecutionOptions::default()
+            },
+        )
+        .await;
+    });
+    Response {
+        status: "202 Accepted",
+        body: serde_json::json!({ "run_id": run_id }),
+    }
+}
+
+/// Compares without short-circuiting so response timing does not leak how
+/// much of the token was right.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Decodes `%XX` escapes so workflow names may contain spaces.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                out.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(raw: &str) -> Result<(String, Vec<u8>), Response> {
+        tauri::async_runtime::block_on(read_request(&mut raw.as_bytes()))
+    }
+
+    #[test]
+    fn tokens_match_only_the_exact_token() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3creT", "s3cret"));
+        assert!(!tokens_match("s3c", "s3cret"));
+        assert!(!tokens_match("", "s3cret"));
+    }
+
+    #[test]
+    fn percent_decode_handles_escapes_and_plus() {
+        assert_eq!(percent_decode("release%20notes"), "release notes");
+        assert_eq!(percent_decode("release+notes"), "release notes");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn reads_head_and_announced_body() {
+        let (head, body) = read(
+            "POST /workflows/daily/run HTTP/1.1\r\nContent-Length: 7\r\n\r\n{\"a\":1}trailing",
+        )
+        .unwrap();
+        assert!(head.starts_with("POST /workflows/daily/run"));
+        assert_eq!(body, b"{\"a\":1}");
+        assert_eq!(header(&head, "CONTENT-LENGTH"), Some("7"));
+    }
+
+    #[test]
+    fn body_is_empty_without_content_length() {
+        let (_, body) = read("POST /workflows/daily/run HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_and_oversized_requests() {
+        let status = |raw: &str| read(raw).unwrap_err().status;
+        assert_eq!(
+            status("POST /workflows/daily/run HTTP/1.1\r\n"),
+            "400 Bad Request"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"),
+            "400 Bad Request"
+        );
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nContent-Length: nope\r\n\r\n"),
+            "400 Bad Request"
+        );
+        assert_eq!(
+            status(&format!(
+                "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                MAX_BODY_BYTES + 1
+            )),
+            "413 Payload Too Large"
+        );
+    }
+}
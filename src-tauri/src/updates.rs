@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/mateim4/SquadAID/releases/latest";
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Setting this (to any value) disables the periodic background check;
+/// `check_for_updates` still works on demand.
+const DISABLE_CHECK_ENV: &str = "SQUADAID_DISABLE_UPDATE_CHECK";
+
+#[derive(Deserialize, Debug)]
+struct GhRelease {
+    tag_name: String,
+    html_url: String,
+    body: Option<String>,
+}
+
+/// @struct UpdateInfo
+/// The payload returned by `check_for_updates`.
+#[derive(Serialize, Debug, Clone)]
+pub struct UpdateInfo {
+    pub current_version: String,
+    pub latest_version: String,
+    pub update_available: bool,
+    pub changelog: String,
+    pub download_url: String,
+}
+
+async fn fetch_update_info(current: &semver::Version) -> Result<UpdateInfo, String> {
+    let client = reqwest::Client::new();
+    let res = client
+        .get(RELEASES_URL)
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "SquadAID-Tauri-App")
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !res.status().is_success() {
+        return Err(format!("GitHub API failed with status: {}", res.status()));
+    }
+    let release: GhRelease = res.json().await.map_err(|e| e.to_string())?;
+    let latest = semver::Version::parse(release.tag_name.trim_start_matches('v'))
+        .map_err(|e| format!("Unrecognized release tag '{}': {}", release.tag_name, e))?;
+
+    Ok(UpdateInfo {
+        current_version: current.to_string(),
+        latest_version: latest.to_string(),
+        update_available: latest > *current,
+        changelog: release.body.unwrap_or_default(),
+        download_url: release.html_url,
+    })
+}
+
+/// # check_for_updates
+/// Compares the running version against the latest GitHub release.
+#[tauri::command]
+pub async fn check_for_updates(app: tauri::AppHandle) -> Result<UpdateInfo, String> {
+    fetch_update_info(&app.package_info().version).await
+}
+
+/// # spawn_periodic_check
+/// Checks once a day in the background and shows a desktop notification the
+/// first time a newer release is seen.
+pub fn spawn_periodic_check(app: tauri::AppHandle) {
+    if std::env::var_os(DISABLE_CHECK_ENV).is_some() {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        let current = app.package_info().version.clone();
+        let mut notified_version: Option<String> = None;
+        loop {
+            match fetch_update_info(&current).await {
+                Ok(info)
+                    if info.update_available
+                        && notified_version.as_deref() != Some(info.latest_version.as_str()) =>
+                {
+                    crate::notifications::notify_desktop(
+                        &app,
+                        "Update available",
+                        &format!("SquadAID {} is available.", info.latest_version),
+                    );
+                    notified_version = Some(info.latest_version);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Update check failed: {}", e),
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,258 @@
+//! Pre-flight checks for workflow graphs.
+//!
+//! [`structural_problems`] covers everything the executor itself depends on
+//! and is what `run_workflow` uses to refuse a graph. The `validate_workflow`
+//! command adds checks against settings only the frontend knows about (which
+//! agents exist and which providers are configured) and reports every problem
+//! at once instead of stopping at the first.
+
+use crate::executor::{self, ErrorPolicy, GraphState, WorkflowGraph};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProblemKind {
+    Cycle,
+    OrphanNode,
+    StartNodes,
+    DanglingEdge,
+    LoopWithoutBackEdge,
+    MapBodyEntry,
+    IgnoredErrorEdge,
+    MissingAgent,
+    UnconfiguredProvider,
+}
+
+/// Errors stop a workflow from running; warnings are worth fixing but the
+/// executor copes with them.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// @struct ValidationProblem
+/// One problem found in a graph, with the nodes (or edges, for
+/// `dangling_edge`) it concerns so the canvas can highlight them.
+#[derive(Serialize, Debug, Clone)]
+pub struct ValidationProblem {
+    pub kind: ProblemKind,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub node_ids: Vec<String>,
+}
+
+impl ValidationProblem {
+    fn error(kind: ProblemKind, message: String, node_ids: Vec<String>) -> Self {
+        ValidationProblem {
+            kind,
+            severity: Severity::Error,
+            message,
+            node_ids,
+        }
+    }
+}
+
+/// # structural_problems
+/// Checks the shape of the graph: loops must close, there must be at least
+/// one start node, and the only cycles allowed are loop back-edges.
+pub(crate) fn structural_problems(graph: &WorkflowGraph) -> Vec<ValidationProblem> {
+    let mut problems = Vec::new();
+
+    let mut open_loops: Vec<String> = graph
+        .loop_bodies
+        .iter()
+        .filter(|(loop_id, body)| !body.is_empty() && !graph.back_sources.contains_key(*loop_id))
+        .map(|(loop_id, _)| loop_id.clone())
+        .collect();
+    open_loops.sort_unstable();
+    for loop_id in open_loops {
+        problems.push(ValidationProblem::error(
+            ProblemKind::LoopWithoutBackEdge,
+            format!(
+                "Loop node '{}' has a body but no edge leading back to it.",
+                loop_id
+            ),
+            vec![loop_id],
+        ));
+    }
+
+    let orphans: Vec<String> = if graph.nodes.len() > 1 {
+        graph
+            .nodes
+            .iter()
+            .filter(|node| {
+                graph.adj_list[&node.id].is_empty()
+                    && !graph.predecessors.contains_key(&node.id)
+                    && !graph.back_sources.contains_key(&node.id)
+            })
+            .map(|node| node.id.clone())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    if !orphans.is_empty() {
+        problems.push(ValidationProblem {
+            kind: ProblemKind::OrphanNode,
+            severity: Severity::Warning,
+            message: format!(
+                "These nodes are not connected to the rest of the workflow and will run on their own: {}.",
+                orphans.join(", ")
+            ),
+            node_ids: orphans,
+        });
+    }
+
+    if !graph.nodes.is_empty() && graph.start_nodes().is_empty() {
+        problems.push(ValidationProblem::error(
+            ProblemKind::StartNodes,
+            "Workflow has no start node (a node with no incoming edges), so nothing can run first."
+                .to_string(),
+            Vec::new(),
+        ));
+    }
+
+    if let Err(blocked) = executor::topological_order(&graph.nodes, &graph.adj_list) {
+        problems.push(ValidationProblem::error(
+            ProblemKind::Cycle,
+            format!(
+                "Workflow contains a cycle; these nodes can never run because they depend on each other: {}. Use a loop node for repetition.",
+                blocked.join(", ")
+            ),
+            blocked,
+        ));
+    }
+
+    for edge_id in &graph.dangling_edges {
+        problems.push(ValidationProblem {
+            kind: ProblemKind::DanglingEdge,
+            severity: Severity::Warning,
+            message: format!(
+                "Edge '{}' points at a node that does not exist and will be ignored.",
+                edge_id
+            ),
+            node_ids: vec![edge_id.clone()],
+        });
+    }
+
+    for edge_id in &graph.map_entry_edges {
+        problems.push(ValidationProblem::error(
+            ProblemKind::MapBodyEntry,
+            format!(
+                "Edge '{}' leads into the body of a map node from outside; body nodes can only be reached through their map node.",
+                edge_id
+            ),
+            vec![edge_id.clone()],
+        ));
+    }
+    let mut map_ids: Vec<&String> = graph.map_bodies.keys().collect();
+    map_ids.sort_unstable();
+    for map_id in map_ids {
+        // Body entry nodes hang off the map node, so they are not orphans.
+        problems.extend(
+            structural_problems(&graph.map_bodies[map_id].graph)
+                .into_iter()
+                .filter(|problem| problem.kind != ProblemKind::OrphanNode),
+        );
+    }
+
+    if graph.error_policy != ErrorPolicy::RouteToErrorHandler {
+        let mut sources: Vec<String> = graph
+            .adj_list
+            .iter()
+            .filter(|(_, successors)| successors.iter().any(|edge| edge.on_error))
+            .map(|(source, _)| source.clone())
+            .collect();
+        sources.sort_unstable();
+        if !sources.is_empty() {
+            problems.push(ValidationProblem {
+                kind: ProblemKind::IgnoredErrorEdge,
+                severity: Severity::Warning,
+                message: format!(
+                    "The workflow's error policy does not route to error handlers, so the on-error edges of these nodes will never be followed: {}.",
+                    sources.join(", ")
+                ),
+                node_ids: sources,
+            });
+        }
+    }
+
+    problems
+}
+
+/// Checks node settings against the agents and providers the frontend has
+/// configured. Either list may be omitted to skip that check.
+fn configuration_problems(
+    graph: &WorkflowGraph,
+    agent_ids: Option<&[String]>,
+    configured_providers: Option<&[String]>,
+) -> Vec<ValidationProblem> {
+    let mut problems = Vec::new();
+    for node in graph.all_nodes() {
+        if let (Some(agent_id), Some(agent_ids)) = (node.data["agentId"].as_str(), agent_ids) {
+            if !agent_ids.iter().any(|id| id == agent_id) {
+                problems.push(ValidationProblem::error(
+                    ProblemKind::MissingAgent,
+                    format!(
+                        "Node '{}' uses agent '{}', which does not exist.",
+                        node.id, agent_id
+                    ),
+                    vec![node.id.clone()],
+                ));
+            }
+        }
+
+        let Some(provider) = node.data["provider"].as_str() else {
+            continue;
+        };
+        if let Some(configured) = configured_providers {
+            if !configured.iter().any(|p| p == provider) {
+                problems.push(ValidationProblem::error(
+                    ProblemKind::UnconfiguredProvider,
+                    format!(
+                        "Node '{}' uses provider '{}', which has not been set up in Settings.",
+                        node.id, provider
+                    ),
+                    vec![node.id.clone()],
+                ));
+                continue;
+            }
+        }
+        if node.data["model"]
+            .as_str()
+            .map_or(true, |m| m.trim().is_empty())
+        {
+            problems.push(ValidationProblem::error(
+                ProblemKind::UnconfiguredProvider,
+                format!(
+                    "Node '{}' uses provider '{}' but no model is selected.",
+                    node.id, provider
+                ),
+                vec![node.id.clone()],
+            ));
+        }
+    }
+    problems
+}
+
+/// # validate_workflow
+/// Runs every pre-flight check on a graph without executing anything and
+/// returns the problems found; an empty list means the workflow can run.
+#[tauri::command]
+pub async fn validate_workflow(
+    graph_state_json: String,
+    agent_ids: Option<Vec<String>>,
+    configured_providers: Option<Vec<String>>,
+) -> Result<Vec<ValidationProblem>, String> {
+    let graph: GraphState = serde_json::from_str(&graph_state_json).map_err(|e| e.to_string())?;
+    let graph = WorkflowGraph::analyze(graph);
+    let mut problems = structural_problems(&graph);
+    problems.extend(configuration_problems(
+        &graph,
+        agent_ids.as_deref(),
+        configured_providers.as_deref(),
+    ));
+    Ok(problems)
+}
@@ -0,0 +1,485 @@
+//! Saved, named workflows stored in the `workflows` table.
+
+use crate::db::Db;
+use crate::executor::{GraphState, ParameterDecl};
+use crate::metrics::AppMetrics;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+
+/// Identifies exported files, so importing some other JSON fails clearly.
+const BUNDLE_FORMAT: &str = "squadaid-workflow";
+const BUNDLE_VERSION: u32 = 1;
+
+/// @struct WorkflowSummary
+/// A row of the workflow list, without the graph itself.
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct WorkflowSummary {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// @struct Workflow
+/// A saved workflow including its graph, as the canvas serialized it.
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct Workflow {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub summary: WorkflowSummary,
+    #[sqlx(rename = "graph_state")]
+    pub graph_state_json: String,
+}
+
+/// @struct WorkflowVersion
+/// One saved state of a workflow's graph. Versions count up from 1.
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct WorkflowVersion {
+    pub version: i64,
+    pub created_at: String,
+}
+
+/// @struct WorkflowBundle
+/// The portable file written by `export_workflow`: the graph plus the agent
+/// definitions its nodes reference, so it works on a machine that has never
+/// seen those agents.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorkflowBundle {
+    pub format: String,
+    pub version: u32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub exported_at: String,
+    pub graph: Value,
+    #[serde(default)]
+    pub agents: Vec<Value>,
+}
+
+/// @struct ImportedWorkflow
+/// Result of `import_workflow`. The frontend registers `agents` with its
+/// agent store, since agents are not kept by the backend.
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportedWorkflow {
+    pub workflow: WorkflowSummary,
+    pub agents: Vec<Value>,
+}
+
+/// @struct ParameterInfo
+/// A workflow parameter as shown in the run dialog.
+#[derive(Serialize, Debug, Clone)]
+pub struct ParameterInfo {
+    #[serde(flatten)]
+    pub decl: ParameterDecl,
+    pub required: bool,
+}
+
+/// Turns a unique-constraint violation on `name` into a readable error.
+fn name_error(name: &str, e: sqlx::Error) -> String {
+    match &e {
+        sqlx::Error::Database(db_error) if db_error.is_unique_violation() => {
+            format!("A workflow named '{}' already exists.", name)
+        }
+        _ => e.to_string(),
+    }
+}
+
+fn validate_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Workflow name must not be empty.".to_string());
+    }
+    Ok(name)
+}
+
+pub async fn fetch_workflow(db: &Db, id: &str) -> Result<Workflow, String> {
+    sqlx::query_as::<_, Workflow>(
+        "SELECT id, name, description, created_at, updated_at, graph_state FROM workflows WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&db.pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("No workflow with id '{}'.", id))
+}
+
+#[tauri::command]
+pub async fn list_workflows(
+    db: tauri::State<'_, Db>,
+    metrics: tauri::State<'_, AppMetrics>,
+) -> Result<Vec<WorkflowSummary>, String> {
+    let query = sqlx::query_as::<_, WorkflowSummary>(
+        "SELECT id, name, description, created_at, updated_at FROM workflows ORDER BY name",
+    );
+    metrics
+        .db_query(query.fetch_all(&db.pool))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// # save_workflow
+/// Saves a graph under `name`, overwriting the workflow of that name if one
+/// exists, and returns the stored workflow's summary. Every save that
+/// changes the graph is also kept as a new version in the workflow's history.
+#[tauri::command]
+pub async fn save_workflow(
+    db: tauri::State<'_, Db>,
+    metrics: tauri::State<'_, AppMetrics>,
+    name: String,
+    graph_state_json: String,
+    description: Option<String>,
+) -> Result<WorkflowSummary, String> {
+    let name = validate_name(&name)?;
+    metrics
+        .db_query(store_workflow(
+            &db,
+            name,
+            &graph_state_json,
+            description.as_deref(),
+        ))
+        .await
+}
+
+async fn store_workflow(
+    db: &Db,
+    name: &str,
+    graph_state_json: &str,
+    description: Option<&str>,
+) -> Result<WorkflowSummary, String> {
+    // Reject graphs the executor could not even parse.
+    serde_json::from_str::<GraphState>(graph_state_json).map_err(|e| e.to_string())?;
+    let now = Utc::now().to_rfc3339();
+    let mut tx = db.pool.begin().await.map_err(|e| e.to_string())?;
+    let summary = sqlx::query_as::<_, WorkflowSummary>(
+        "INSERT INTO workflows (id, name, description, graph_state, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+         ON CONFLICT (name) DO UPDATE SET
+            description = COALESCE(excluded.description, workflows.description),
+            graph_state = excluded.graph_state,
+            updated_at = excluded.updated_at
+         RETURNING id, name, description, created_at, updated_at",
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(name)
+    .bind(description)
+    .bind(graph_state_json)
+    .bind(&now)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    insert_version(&mut tx, &summary.id, graph_state_json, &now).await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(summary)
+}
+
+/// Appends the next version of a workflow's graph to its history, unless it
+/// is the same graph as the latest version.
+async fn insert_version(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    workflow_id: &str,
+    graph_state_json: &str,
+    now: &str,
+) -> Result<(), String> {
+    let latest: Option<String> = sqlx::query_scalar(
+        "SELECT graph_state FROM workflow_versions WHERE workflow_id = ?
+         ORDER BY version DESC LIMIT 1",
+    )
+    .bind(workflow_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    if latest.as_deref() == Some(graph_state_json) {
+        return Ok(());
+    }
+    sqlx::query(
+        "INSERT INTO workflow_versions (workflow_id, version, graph_state, created_at)
+         SELECT ?1, COALESCE(MAX(version), 0) + 1, ?2, ?3
+         FROM workflow_versions WHERE workflow_id = ?1",
+    )
+    .bind(workflow_id)
+    .bind(graph_state_json)
+    .bind(now)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn load_workflow(
+    db: tauri::State<'_, Db>,
+    metrics: tauri::State<'_, AppMetrics>,
+    id: String,
+) -> Result<Workflow, String> {
+    metrics.db_query(fetch_workflow(&db, &id)).await
+}
+
+#[tauri::command]
+pub async fn rename_workflow(
+    db: tauri::State<'_, Db>,
+    metrics: tauri::State<'_, AppMetrics>,
+    id: String,
+    name: String,
+) -> Result<(), String> {
+    let name = validate_name(&name)?;
+    let query = sqlx::query("UPDATE workflows SET name = ?, updated_at = ? WHERE id = ?")
+        .bind(name)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&id);
+    let updated = metrics
+        .db_query(query.execute(&db.pool))
+        .await
+        .map_err(|e| name_error(name, e))?;
+    if updated.rows_affected() == 0 {
+        return Err(format!("No workflow with id '{}'.", id));
+    }
+    Ok(())
+}
+
+/// # delete_workflow
+/// Deletes a saved workflow. Its past runs stay in the run history.
+#[tauri::command]
+pub async fn delete_workflow(
+    db: tauri::State<'_, Db>,
+    metrics: tauri::State<'_, AppMetrics>,
+    id: String,
+) -> Result<(), String> {
+    let query = sqlx::query("DELETE FROM workflows WHERE id = ?").bind(&id);
+    let deleted = metrics
+        .db_query(query.execute(&db.pool))
+        .await
+        .map_err(|e| e.to_string())?;
+    if deleted.rows_affected() == 0 {
+        return Err(format!("No workflow with id '{}'.", id));
+    }
+    Ok(())
+}
+
+/// # get_workflow_versions
+/// Lists a workflow's saved versions, newest first.
+#[tauri::command]
+pub async fn get_workflow_versions(
+    db: tauri::State<'_, Db>,
+    metrics: tauri::State<'_, AppMetrics>,
+    workflow_id: String,
+) -> Result<Vec<WorkflowVersion>, String> {
+    metrics.db_query(fetch_workflow(&db, &workflow_id)).await?;
+    let query = sqlx::query_as::<_, WorkflowVersion>(
+        "SELECT version, created_at FROM workflow_versions
+         WHERE workflow_id = ? ORDER BY version DESC",
+    )
+    .bind(&workflow_id);
+    metrics
+        .db_query(query.fetch_all(&db.pool))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// # restore_workflow_version
+/// Makes an earlier version the workflow's current graph and returns it.
+/// The restore is itself recorded as a new version, so it can be undone.
+#[tauri::command]
+pub async fn restore_workflow_version(
+    db: tauri::State<'_, Db>,
+    workflow_id: String,
+    version: i64,
+) -> Result<Workflow, String> {
+    let mut tx = db.pool.begin().await.map_err(|e| e.to_string())?;
+    let graph_state_json: String = sqlx::query_scalar(
+        "SELECT graph_state FROM workflow_versions WHERE workflow_id = ? AND version = ?",
+    )
+    .bind(&workflow_id)
+    .bind(version)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Workflow '{}' has no version {}.", workflow_id, version))?;
+
+    let now = Utc::now().to_rfc3339();
+    sqlx::query("UPDATE workflows SET graph_state = ?, updated_at = ? WHERE id = ?")
+        .bind(&graph_state_json)
+        .bind(&now)
+        .bind(&workflow_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    insert_version(&mut tx, &workflow_id, &graph_state_json, &now).await?;
+    tx.commit().await.map_err(|e| e.to_string())?;
+    fetch_workflow(&db, &workflow_id).await
+}
+
+/// # export_workflow
+/// Writes a saved workflow to `path` as a self-contained JSON bundle.
+/// `agents` are the frontend's agent definitions; only those referenced by a
+/// node's `agentId` are included.
+#[tauri::command]
+pub async fn export_workflow(
+    db: tauri::State<'_, Db>,
+    workflow_id: String,
+    path: String,
+    agents: Option<Vec<Value>>,
+) -> Result<(), String> {
+    let workflow = fetch_workflow(&db, &workflow_id).await?;
+    let graph: Value =
+        serde_json::from_str(&workflow.graph_state_json).map_err(|e| e.to_string())?;
+    let referenced: HashSet<&str> = graph["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|node| node["data"]["agentId"].as_str())
+        .collect();
+    let agents = agents
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|agent| {
+            agent["id"]
+                .as_str()
+                .map_or(false, |id| referenced.contains(id))
+        })
+        .collect();
+
+    let bundle = WorkflowBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        name: workflow.summary.name,
+        description: workflow.summary.description,
+        exported_at: Utc::now().to_rfc3339(),
+        graph,
+        agents,
+    };
+    let json = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+    fs::write(&path, json).map_err(|e| e.to_string())
+}
+
+/// # import_workflow
+/// Saves the workflow in a bundle written by `export_workflow`. A name that
+/// is already taken gets a numeric suffix rather than overwriting anything.
+#[tauri::command]
+pub async fn import_workflow(
+    db: tauri::State<'_, Db>,
+    path: String,
+) -> Result<ImportedWorkflow, String> {
+    let json = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let bundle: WorkflowBundle = serde_json::from_str(&json)
+        .map_err(|e| format!("'{}' is not a workflow export: {}", path, e))?;
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(format!("'{}' is not a workflow export.", path));
+    }
+    if bundle.version > BUNDLE_VERSION {
+        return Err(format!(
+            "'{}' was exported by a newer version of SquadAID (format version {}).",
+            path, bundle.version
+        ));
+    }
+
+    let base = validate_name(&bundle.name)?;
+    let taken: HashSet<String> = sqlx::query_scalar("SELECT name FROM workflows")
+        .fetch_all(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .collect();
+    let name = (1..)
+        .map(|n| match n {
+            1 => base.to_string(),
+            n => format!("{} ({})", base, n),
+        })
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap_or_else(|| base.to_string());
+
+    let workflow = store_workflow(
+        &db,
+        &name,
+        &bundle.graph.to_string(),
+        bundle.description.as_deref(),
+    )
+    .await?;
+    Ok(ImportedWorkflow {
+        workflow,
+        agents: bundle.agents,
+    })
+}
+
+/// # get_workflow_parameters
+/// Lists the parameters a saved workflow accepts, from its declarations and
+/// the `{{params.<name>}}` templates its nodes use.
+#[tauri::command]
+pub async fn get_workflow_parameters(
+    db: tauri::State<'_, Db>,
+    workflow_id: String,
+) -> Result<Vec<ParameterInfo>, String> {
+    let workflow = fetch_workflow(&db, &workflow_id).await?;
+    let graph: GraphState =
+        serde_json::from_str(&workflow.graph_state_json).map_err(|e| e.to_string())?;
+    Ok(graph
+        .parameters()
+        .into_iter()
+        .map(|decl| ParameterInfo {
+            required: decl.default.is_none(),
+            decl,
+        })
+        .collect())
+}
+
+/// Node ids of a workflow's breakpoints, for a run started in step mode.
+pub async fn breakpoints(db: &Db, workflow_id: &str) -> Result<HashSet<String>, String> {
+    let node_ids: Vec<String> =
+        sqlx::query_scalar("SELECT node_id FROM workflow_breakpoints WHERE workflow_id = ?")
+            .bind(workflow_id)
+            .fetch_all(&db.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+    Ok(node_ids.into_iter().collect())
+}
+
+/// # set_breakpoint
+/// Flags a node so that runs started in step mode stop before it until
+/// `step_run` is called. Setting an existing breakpoint does nothing.
+#[tauri::command]
+pub async fn set_breakpoint(
+    db: tauri::State<'_, Db>,
+    workflow_id: String,
+    node_id: String,
+) -> Result<(), String> {
+    fetch_workflow(&db, &workflow_id).await?;
+    sqlx::query("INSERT OR IGNORE INTO workflow_breakpoints (workflow_id, node_id) VALUES (?, ?)")
+        .bind(&workflow_id)
+        .bind(&node_id)
+        .execute(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// # clear_breakpoint
+/// Removes a breakpoint set with `set_breakpoint`.
+#[tauri::command]
+pub async fn clear_breakpoint(
+    db: tauri::State<'_, Db>,
+    workflow_id: String,
+    node_id: String,
+) -> Result<(), String> {
+    sqlx::query("DELETE FROM workflow_breakpoints WHERE workflow_id = ? AND node_id = ?")
+        .bind(&workflow_id)
+        .bind(&node_id)
+        .execute(&db.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// # get_breakpoints
+/// Lists the nodes of a workflow that have a breakpoint.
+#[tauri::command]
+pub async fn get_breakpoints(
+    db: tauri::State<'_, Db>,
+    workflow_id: String,
+) -> Result<Vec<String>, String> {
+    let mut node_ids: Vec<String> = breakpoints(&db, &workflow_id).await?.into_iter().collect();
+    node_ids.sort_unstable();
+    Ok(node_ids)
+}
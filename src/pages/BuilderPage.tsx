@@ -6,7 +6,6 @@ import ReactFlow, {
   ReactFlowProvider,
   useReactFlow,
 } from 'reactflow';
-import { useDebouncedCallback } from 'use-debounce';
 import { invoke } from '@tauri-apps/api/tauri';
 import { v4 as uuidv4 } from 'uuid';
 
@@ -29,14 +28,12 @@ const BuilderPageContent = memo(() => {
     onConnect,
     setFlow,
     addNode, // Get addNode from the store
-    saveFlow,
   } = useFlowStore(selector);
 
   // A ref to the React Flow wrapper is needed to get canvas bounds
   const reactFlowWrapper = useRef<HTMLDivElement>(null);
   // The useReactFlow hook provides the instance, including the `project` method
   const { project } = useReactFlow();
-  const debouncedSave = useDebouncedCallback(saveFlow, 1000);
   const nodeTypes = useMemo(
     () => ({
       assistantAgent: AssistantAgentNode,
@@ -109,7 +106,6 @@ const BuilderPageContent = memo(() => {
         onNodesChange={onNodesChange}
         onEdgesChange={onEdgesChange}
         onConnect={onConnect}
-        onMove={debouncedSave}
         nodeTypes={nodeTypes}
         fitView
       >
@@ -13,7 +13,6 @@ import {
   applyEdgeChanges,
   Viewport,
 } from 'reactflow';
-import { invoke } from '@tauri-apps/api/tauri';
 
 export interface FlowState {
   nodes: Node[];
@@ -24,7 +23,6 @@ export interface FlowState {
   onConnect: OnConnect;
   setFlow: (flow: { nodes: Node[]; edges: Edge[]; viewport: Viewport }) => void;
   addNode: (node: Node) => void; // Add the new action type
-  saveFlow: () => void;
 }
 
 const useFlowStore = create<FlowState>((set, get) => ({
@@ -65,15 +63,6 @@ const useFlowStore = create<FlowState>((set, get) => ({
   addNode: (node: Node) => {
     set((state) => ({ nodes: [...state.nodes, node] }));
   },
-
-  saveFlow: () => {
-    const { nodes, edges, viewport } = get();
-    const graphState = { nodes, edges, viewport };
-    invoke('save_workflow', {
-      id: 1,
-      graphStateJson: JSON.stringify(graphState),
-    }).catch(console.error);
-  },
 }));
 
 export default useFlowStore;
\ No newline at end of file